@@ -0,0 +1,18 @@
+//! What the enforce loop needs of a sound system, so that it does not depend on alsa
+
+use crate::error::PttError;
+
+/// The capture of a device, like the controls of an alsa mixer or a PulseAudio source, owned by the enforce thread of the device
+pub trait AudioBackend {
+    /// Switches the capture on or off
+    fn set_capture(&mut self, enabled: bool) -> Result<(), PttError>;
+
+    /// Whether the capture is on, or `None` if its channels or controls disagree
+    fn get_capture(&self) -> Result<Option<bool>, PttError>;
+
+    /// Waits until something else changes the capture, or until the wake-up descriptor of the enforce thread becomes readable
+    fn wait_for_external_change(&mut self, wake_up: libc::pollfd) -> Result<(), PttError>;
+
+    /// Opens the device anew, e.g. after it failed or after resuming from suspend
+    fn reopen(&mut self) -> Result<(), PttError>;
+}
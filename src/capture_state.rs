@@ -0,0 +1,142 @@
+//! The capture state asked for by the hotkeys, handed over to the threads that own the mixers
+//!
+//! The alsa mixer handle is not `Sync`, so only the enforce thread of each device uses it. The other threads just store
+//! the capture state they want and wake up the enforce threads, which are polling their mixer together with a pipe for
+//! the wake-ups. They then set the state on the mixer, and keep it that way when something else changes it.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+pub struct ExpectedCaptureState {
+    state: AtomicBool,
+    /// increased whenever the enforce threads should reopen their mixers
+    reopen_generation: AtomicU32,
+    /// the read and write end of a pipe for each enforce thread
+    wake_pipes: Vec<(OwnedFd, OwnedFd)>,
+}
+
+impl ExpectedCaptureState {
+    /// The enforce threads are numbered from 0 up to `enforcers`
+    pub fn new(state: bool, enforcers: usize) -> io::Result<ExpectedCaptureState> {
+        let wake_pipes = (0..enforcers).map(|_| {
+            let mut fds = [0; 2];
+            // non-blocking, so that neither waking up nor clearing the wake-ups can get stuck
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+        }).collect::<io::Result<_>>()?;
+        Ok(ExpectedCaptureState { state: AtomicBool::new(state), reopen_generation: AtomicU32::new(0), wake_pipes })
+    }
+
+    pub fn get(&self) -> bool {
+        self.state.load(Ordering::Acquire)
+    }
+
+    /// Stores the state and wakes up the enforce threads, returns whether the state changed
+    pub fn set(&self, state: bool) -> bool {
+        let previous = self.state.swap(state, Ordering::AcqRel);
+        self.wake();
+        previous != state
+    }
+
+    /// Makes the enforce threads check the state, also when it has not changed
+    pub fn wake(&self) {
+        for (_, wake_write) in &self.wake_pipes {
+            // a full pipe already has wake-ups pending, so a failed write is fine
+            unsafe { libc::write(wake_write.as_raw_fd(), [1u8].as_ptr().cast(), 1) };
+        }
+    }
+
+    /// Makes the enforce threads reopen their mixers, e.g. after resuming from suspend when the device may have been reset
+    pub fn request_reopen(&self) {
+        self.reopen_generation.fetch_add(1, Ordering::AcqRel);
+        self.wake();
+    }
+
+    /// Compared by the enforce threads with the one from when they opened their mixer
+    pub fn reopen_generation(&self) -> u32 {
+        self.reopen_generation.load(Ordering::Acquire)
+    }
+
+    /// For polling together with the mixer, readable after a wake-up
+    pub fn poll_descriptor(&self, enforcer: usize) -> libc::pollfd {
+        libc::pollfd { fd: self.wake_pipes[enforcer].0.as_raw_fd(), events: libc::POLLIN, revents: 0 }
+    }
+
+    /// Called by the enforce thread after polling, before reading the state
+    pub fn clear_wake_ups(&self, enforcer: usize) {
+        let mut buf = [0u8; 64];
+        while unsafe { libc::read(self.wake_pipes[enforcer].0.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    fn woken(state: &ExpectedCaptureState, enforcer: usize, timeout_ms: i32) -> bool {
+        let mut fds = [state.poll_descriptor(enforcer)];
+        unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms) == 1 }
+    }
+
+    #[test]
+    fn set_reports_changes() {
+        let state = ExpectedCaptureState::new(false, 1).unwrap();
+        assert!(!state.get());
+        assert!(state.set(true));
+        assert!(state.get());
+        assert!(!state.set(true));
+        assert!(state.set(false));
+        assert!(!state.get());
+    }
+
+    #[test]
+    fn set_wakes_until_cleared() {
+        let state = ExpectedCaptureState::new(false, 1).unwrap();
+        assert!(!woken(&state, 0, 0));
+        state.set(true);
+        state.set(true);
+        assert!(woken(&state, 0, 0));
+        state.clear_wake_ups(0);
+        assert!(!woken(&state, 0, 0));
+    }
+
+    #[test]
+    fn set_wakes_each_enforcer() {
+        let state = ExpectedCaptureState::new(false, 2).unwrap();
+        state.set(true);
+        state.clear_wake_ups(0);
+        assert!(!woken(&state, 0, 0));
+        assert!(woken(&state, 1, 0));
+    }
+
+    #[test]
+    fn request_reopen_wakes() {
+        let state = ExpectedCaptureState::new(true, 1).unwrap();
+        let generation = state.reopen_generation();
+        state.request_reopen();
+        assert!(woken(&state, 0, 0));
+        assert_ne!(state.reopen_generation(), generation);
+        assert!(state.get());
+    }
+
+    #[test]
+    fn set_wakes_other_thread() {
+        let state = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let enforce_thread = {
+            let state = state.clone();
+            thread::spawn(move || {
+                assert!(woken(&state, 0, 5000));
+                state.clear_wake_ups(0);
+                state.get()
+            })
+        };
+        state.set(true);
+        assert!(enforce_thread.join().unwrap());
+    }
+}
@@ -0,0 +1,469 @@
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use regex::Regex;
+use serde::Deserialize;
+use xcb::x::{Keycode, ModMask};
+
+use push_to_talk::{parse_hotkey_spec, parse_modifiers, AudioBackend, ExitState, Hotkey, InputBackend, Method, Mode, ResumeState};
+use push_to_talk::mixer::ControlId;
+use push_to_talk::profile::Profile;
+use push_to_talk::state_file::StateFileFormat;
+use push_to_talk::state_reporter::StateReporter;
+#[cfg(feature = "led")]
+use push_to_talk::LockLed;
+
+use crate::{Args, LogLevel};
+
+/// Contents of the config file, keys named like the fields of `Args`
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    device: Option<OneOrMany>,
+    control: Option<OneOrMany>,
+    control_index: Option<u32>,
+    method: Option<Method>,
+    use_volume: Option<bool>,
+    capture_volume: Option<u8>,
+    muted_volume: Option<u8>,
+    restore_volume: Option<bool>,
+    playback_control: Option<String>,
+    unmute_delay: Option<u64>,
+    #[serde(alias = "mute_delay")]
+    remute_delay: Option<u64>,
+    debounce: Option<u64>,
+    push_modifiers: Option<String>,
+    push_keycode: Option<Vec<Keycode>>,
+    push_keysym: Option<String>,
+    push_hotkey: Option<Vec<String>>,
+    unmute_key: Option<Vec<String>>,
+    mute_key: Option<Vec<String>>,
+    panic_key: Option<String>,
+    push_button: Option<u32>,
+    allow_bare_button: Option<bool>,
+    toggle_modifiers: Option<String>,
+    toggle_keycode: Option<Vec<Keycode>>,
+    toggle_keysym: Option<String>,
+    strict_modifiers: Option<bool>,
+    ignore_lock_modifiers: Option<bool>,
+    mode: Option<Mode>,
+    toggle: Option<bool>,
+    tap_toggle_threshold: Option<u32>,
+    double_tap_lock: Option<u32>,
+    min_hold: Option<u32>,
+    max_unmute_ms: Option<u64>,
+    max_talk: Option<u64>,
+    auto_mute_idle: Option<u64>,
+    #[serde(alias = "push_to_mute")]
+    invert: Option<bool>,
+    beep: Option<bool>,
+    sound_on: Option<PathBuf>,
+    sound_off: Option<PathBuf>,
+    open_mic_reminder: Option<u64>,
+    reminder_sound: Option<PathBuf>,
+    #[serde(alias = "sound_device")]
+    beep_device: Option<String>,
+    no_repeat_filter: Option<bool>,
+    no_stuck_key_check: Option<bool>,
+    auto_mute_on_focus_loss: Option<bool>,
+    reconnect: Option<bool>,
+    no_reconnect: Option<bool>,
+    x11_reconnect_attempts: Option<u32>,
+    resume_state: Option<ResumeState>,
+    alsa_retry_limit: Option<u32>,
+    exit_state: Option<ExitState>,
+    restore_on_exit: Option<bool>,
+    pid_file: Option<PathBuf>,
+    no_sd_notify: Option<bool>,
+    log_level: Option<LogLevel>,
+    measure_latency: Option<bool>,
+    input_backend: Option<InputBackend>,
+    audio_backend: Option<AudioBackend>,
+    display: Option<String>,
+    xauthority: Option<PathBuf>,
+    passive: Option<bool>,
+    input_device: Option<String>,
+    grab_retries: Option<u32>,
+    grab_retry_interval: Option<u64>,
+    grab_fallback: Option<bool>,
+    control_socket: Option<PathBuf>,
+    status_socket: Option<PathBuf>,
+    signal_unmute_delay: Option<u64>,
+    state_file: Option<PathBuf>,
+    state_file_format: Option<StateFileFormat>,
+    output: Option<StateReporter>,
+    json_output: Option<bool>,
+    #[cfg(feature = "notifications")]
+    #[serde(alias = "notifications")]
+    notify: Option<bool>,
+    #[cfg(feature = "led")]
+    led_on: Option<u8>,
+    #[cfg(feature = "led")]
+    led_off: Option<u8>,
+    #[cfg(feature = "led")]
+    led: Option<LockLed>,
+    #[cfg(feature = "dbus")]
+    dbus: Option<bool>,
+    /// window class patterns with their own device and control, kept as a table to try them in the order of the file
+    profiles: Option<toml::Table>,
+}
+
+/// A `[profiles]` entry, keyed by its window class pattern
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ProfileEntry {
+    device: String,
+    control: String,
+}
+
+/// Parses the commandline arguments, using values from the config file for anything not given on the commandline
+pub fn load_args() -> Args {
+    let mut command = Args::command();
+    let matches = command.get_matches_mut();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Err(e) = apply_config_file(&mut args, &matches) {
+        command.error(ErrorKind::InvalidValue, e).exit();
+    }
+    if args.toggle {
+        args.mode = Mode::Toggle;
+    }
+    if args.use_volume {
+        args.method = Method::Volume;
+    }
+    if args.restore_on_exit {
+        args.exit_state = ExitState::Restore;
+    }
+    if args.json_output {
+        args.output = StateReporter::JsonEvents;
+    }
+    if let Some(max_talk) = args.max_talk {
+        args.max_unmute_ms = Some(max_talk * 1000);
+    }
+    // a single control is used for all devices, and several controls of a single device are muted together
+    let controls = args.control.iter()
+        .map(|control| split_controls(control, args.control_index))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| command.error(ErrorKind::InvalidValue, format!("--control: {}", e)).exit());
+    args.device_controls = if args.device.len() == 1 {
+        vec![controls.concat()]
+    } else if controls.len() == 1 {
+        vec![controls[0].clone(); args.device.len()]
+    } else if controls.len() == args.device.len() {
+        controls
+    } else {
+        command.error(ErrorKind::WrongNumberOfValues, format!("--control given {} times for {} devices, give it once for all devices or once per --device", args.control.len(), args.device.len())).exit();
+    };
+    if args.push_button.is_some() && args.push_modifiers.is_empty() && !args.allow_bare_button {
+        command.error(ErrorKind::ArgumentConflict, "--push-button without --push-modifiers would swallow all clicks of the button, use --allow-bare-button if that is intended").exit();
+    }
+    args
+}
+
+/// Splits a comma-separated list of controls muted together, like `Mic,Mic Boost`, where a number is the index of the name before it
+fn split_controls(control: &str, default_index: u32) -> Result<Vec<ControlId>, String> {
+    let mut controls: Vec<ControlId> = Vec::new();
+    for part in control.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        match part.parse::<u32>() {
+            Ok(index) => controls.last_mut().ok_or_else(|| format!("expected a control name before the index {}", index))?.index = index,
+            Err(_) => controls.push(ControlId::new(part, default_index)),
+        }
+    }
+    Ok(controls)
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("push-to-talk").join("config.toml"))
+}
+
+fn apply_config_file(args: &mut Args, matches: &ArgMatches) -> Result<(), String> {
+    // a missing file is only an error if explicitly asked for
+    let (path, required) = match &args.config {
+        Some(path) => (path.clone(), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(()),
+        },
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if !required && e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Failed to read config file {}: {}", path.display(), e)),
+    };
+    let config: ConfigFile = toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+    config.apply(args, matches).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
+
+/// Overwrites the argument with the config file value, unless any of the listed arguments was given on the commandline
+macro_rules! merge {
+    ($matches:ident, [$($id:literal),+], $target:expr, $value:expr) => {
+        if let Some(value) = $value {
+            if !given_on_command_line($matches, &[$($id),+]) {
+                $target = value;
+            }
+        }
+    };
+}
+
+impl ConfigFile {
+    /// The checks the commandline does with clap, for the keys on their own
+    fn validate(&self) -> Result<(), String> {
+        if self.push_keycode.is_some() && self.push_keysym.is_some() {
+            return Err("push_keycode and push_keysym cannot be used together".into());
+        }
+        if self.toggle_keycode.is_some() && self.toggle_keysym.is_some() {
+            return Err("toggle_keycode and toggle_keysym cannot be used together".into());
+        }
+        if self.method.is_some() && self.use_volume.is_some() {
+            return Err("method and use_volume cannot be used together".into());
+        }
+        if self.capture_volume.is_some() && self.restore_volume == Some(true) {
+            return Err("capture_volume and restore_volume cannot be used together".into());
+        }
+        if self.mode.is_some() && self.toggle.is_some() {
+            return Err("mode and toggle cannot be used together".into());
+        }
+        if self.exit_state.is_some() && self.restore_on_exit.is_some() {
+            return Err("exit_state and restore_on_exit cannot be used together".into());
+        }
+        if self.max_unmute_ms.is_some() && self.max_talk.is_some() {
+            return Err("max_unmute_ms and max_talk cannot be used together".into());
+        }
+        if self.output.is_some() && self.json_output.is_some() {
+            return Err("output and json_output cannot be used together".into());
+        }
+        if self.x11_reconnect_attempts == Some(0) {
+            return Err("x11_reconnect_attempts: expected at least 1".into());
+        }
+        if self.open_mic_reminder == Some(0) {
+            return Err("open_mic_reminder: expected at least 1".into());
+        }
+        if self.reminder_sound.is_some() && self.open_mic_reminder.is_none() {
+            return Err("reminder_sound needs open_mic_reminder".into());
+        }
+        for (key, percent) in [("capture_volume", self.capture_volume), ("muted_volume", self.muted_volume)] {
+            if percent.is_some_and(|percent| percent > 100) {
+                return Err(format!("{}: expected a percentage 0-100", key));
+            }
+        }
+        #[cfg(feature = "led")]
+        for (key, index) in [("led_on", self.led_on), ("led_off", self.led_off)] {
+            if index.is_some_and(|index| index >= 8) {
+                return Err(format!("{}: expected an LED index 0-7", key));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(self, args: &mut Args, matches: &ArgMatches) -> Result<(), String> {
+        self.validate()?;
+        let push_modifiers = parse_optional_modifiers("push_modifiers", self.push_modifiers)?;
+        let toggle_modifiers = parse_optional_modifiers("toggle_modifiers", self.toggle_modifiers)?;
+        let push_hotkey = parse_optional_hotkeys("push_hotkey", self.push_hotkey)?;
+        let unmute_key = parse_optional_hotkeys("unmute_key", self.unmute_key)?;
+        let mute_key = parse_optional_hotkeys("mute_key", self.mute_key)?;
+        let panic_key = self.panic_key.map(|spec| parse_hotkey_spec(&spec).map_err(|e| format!("panic_key: {}", e))).transpose()?;
+        let profiles = self.profiles.map(parse_profiles).transpose()?;
+
+        merge!(matches, ["device"], args.device, self.device.map(OneOrMany::into_vec));
+        merge!(matches, ["control"], args.control, self.control.map(OneOrMany::into_vec));
+        merge!(matches, ["control_index"], args.control_index, self.control_index);
+        merge!(matches, ["method", "use_volume"], args.method, self.method);
+        merge!(matches, ["method", "use_volume"], args.use_volume, self.use_volume);
+        merge!(matches, ["capture_volume"], args.capture_volume, self.capture_volume.map(Some));
+        merge!(matches, ["muted_volume"], args.muted_volume, self.muted_volume);
+        merge!(matches, ["restore_volume"], args.restore_volume, self.restore_volume);
+        merge!(matches, ["playback_control"], args.playback_control, self.playback_control.map(Some));
+        merge!(matches, ["unmute_delay"], args.unmute_delay, self.unmute_delay);
+        merge!(matches, ["remute_delay"], args.remute_delay, self.remute_delay);
+        merge!(matches, ["debounce"], args.debounce, self.debounce);
+        merge!(matches, ["push_modifiers"], args.push_modifiers, push_modifiers);
+        // keycode and keysym are alternatives, so either one on the commandline overrides both in the file
+        merge!(matches, ["push_keycode", "push_keysym"], args.push_keycode, self.push_keycode);
+        merge!(matches, ["push_keycode", "push_keysym"], args.push_keysym, self.push_keysym.map(Some));
+        merge!(matches, ["push_hotkey"], args.push_hotkey, push_hotkey);
+        merge!(matches, ["unmute_key"], args.unmute_key, unmute_key);
+        merge!(matches, ["mute_key"], args.mute_key, mute_key);
+        merge!(matches, ["panic_key"], args.panic_key, panic_key.map(Some));
+        merge!(matches, ["push_button"], args.push_button, self.push_button.map(Some));
+        merge!(matches, ["allow_bare_button"], args.allow_bare_button, self.allow_bare_button);
+        merge!(matches, ["toggle_modifiers"], args.toggle_modifiers, toggle_modifiers);
+        merge!(matches, ["toggle_keycode", "toggle_keysym"], args.toggle_keycode, self.toggle_keycode);
+        merge!(matches, ["toggle_keycode", "toggle_keysym"], args.toggle_keysym, self.toggle_keysym.map(Some));
+        merge!(matches, ["strict_modifiers"], args.strict_modifiers, self.strict_modifiers);
+        merge!(matches, ["ignore_lock_modifiers"], args.ignore_lock_modifiers, self.ignore_lock_modifiers);
+        merge!(matches, ["mode", "toggle"], args.mode, self.mode);
+        merge!(matches, ["mode", "toggle"], args.toggle, self.toggle);
+        merge!(matches, ["tap_toggle_threshold"], args.tap_toggle_threshold, self.tap_toggle_threshold);
+        merge!(matches, ["double_tap_lock"], args.double_tap_lock, self.double_tap_lock);
+        merge!(matches, ["min_hold"], args.min_hold, self.min_hold);
+        merge!(matches, ["max_unmute_ms", "max_talk"], args.max_unmute_ms, self.max_unmute_ms.map(Some));
+        merge!(matches, ["max_unmute_ms", "max_talk"], args.max_talk, self.max_talk.map(Some));
+        merge!(matches, ["auto_mute_idle"], args.auto_mute_idle, self.auto_mute_idle);
+        merge!(matches, ["invert"], args.invert, self.invert);
+        merge!(matches, ["beep"], args.beep, self.beep);
+        merge!(matches, ["sound_on"], args.sound_on, self.sound_on.map(Some));
+        merge!(matches, ["sound_off"], args.sound_off, self.sound_off.map(Some));
+        merge!(matches, ["open_mic_reminder"], args.open_mic_reminder, self.open_mic_reminder.map(Some));
+        merge!(matches, ["reminder_sound"], args.reminder_sound, self.reminder_sound.map(Some));
+        merge!(matches, ["beep_device"], args.beep_device, self.beep_device.map(Some));
+        merge!(matches, ["no_repeat_filter"], args.no_repeat_filter, self.no_repeat_filter);
+        merge!(matches, ["no_stuck_key_check"], args.no_stuck_key_check, self.no_stuck_key_check);
+        merge!(matches, ["auto_mute_on_focus_loss"], args.auto_mute_on_focus_loss, self.auto_mute_on_focus_loss);
+        merge!(matches, ["reconnect"], args.reconnect, self.reconnect);
+        merge!(matches, ["no_reconnect"], args.no_reconnect, self.no_reconnect);
+        merge!(matches, ["x11_reconnect_attempts"], args.x11_reconnect_attempts, self.x11_reconnect_attempts.map(Some));
+        merge!(matches, ["resume_state"], args.resume_state, self.resume_state);
+        merge!(matches, ["alsa_retry_limit"], args.alsa_retry_limit, self.alsa_retry_limit.map(Some));
+        merge!(matches, ["exit_state", "restore_on_exit"], args.exit_state, self.exit_state);
+        merge!(matches, ["exit_state", "restore_on_exit"], args.restore_on_exit, self.restore_on_exit);
+        merge!(matches, ["pid_file"], args.pid_file, self.pid_file.map(Some));
+        merge!(matches, ["no_sd_notify"], args.no_sd_notify, self.no_sd_notify);
+        merge!(matches, ["log_level"], args.log_level, self.log_level);
+        merge!(matches, ["measure_latency"], args.measure_latency, self.measure_latency);
+        merge!(matches, ["input_backend"], args.input_backend, self.input_backend);
+        merge!(matches, ["audio_backend"], args.audio_backend, self.audio_backend);
+        merge!(matches, ["display"], args.display, self.display.map(Some));
+        merge!(matches, ["xauthority"], args.xauthority, self.xauthority.map(Some));
+        merge!(matches, ["passive"], args.passive, self.passive);
+        merge!(matches, ["input_device"], args.input_device, self.input_device.map(Some));
+        merge!(matches, ["grab_retries"], args.grab_retries, self.grab_retries);
+        merge!(matches, ["grab_retry_interval"], args.grab_retry_interval, self.grab_retry_interval);
+        merge!(matches, ["grab_fallback"], args.grab_fallback, self.grab_fallback);
+        merge!(matches, ["control_socket"], args.control_socket, self.control_socket.map(Some));
+        merge!(matches, ["status_socket"], args.status_socket, self.status_socket.map(Some));
+        merge!(matches, ["signal_unmute_delay"], args.signal_unmute_delay, self.signal_unmute_delay);
+        merge!(matches, ["state_file"], args.state_file, self.state_file.map(Some));
+        merge!(matches, ["state_file_format"], args.state_file_format, self.state_file_format);
+        merge!(matches, ["output", "json_output"], args.output, self.output);
+        merge!(matches, ["output", "json_output"], args.json_output, self.json_output);
+        #[cfg(feature = "notifications")]
+        merge!(matches, ["notify"], args.notify, self.notify);
+        #[cfg(feature = "led")]
+        merge!(matches, ["led_on"], args.led_on, self.led_on.map(Some));
+        #[cfg(feature = "led")]
+        merge!(matches, ["led_off"], args.led_off, self.led_off.map(Some));
+        #[cfg(feature = "led")]
+        merge!(matches, ["led"], args.led, self.led.map(Some));
+        #[cfg(feature = "dbus")]
+        merge!(matches, ["dbus"], args.dbus, self.dbus);
+        // only in the config file
+        args.profiles = profiles.unwrap_or_default();
+        Ok(())
+    }
+}
+
+/// A single value, or a list of them
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+fn parse_optional_modifiers(key: &str, value: Option<String>) -> Result<Option<ModMask>, String> {
+    value.map(|value| parse_modifiers(&value).map_err(|e| format!("{}: {}", key, e))).transpose()
+}
+
+fn parse_optional_hotkeys(key: &str, value: Option<Vec<String>>) -> Result<Option<Vec<Hotkey>>, String> {
+    value.map(|specs| specs.iter().map(|spec| parse_hotkey_spec(spec).map_err(|e| format!("{}: {}", key, e))).collect()).transpose()
+}
+
+fn parse_profiles(table: toml::Table) -> Result<Vec<Profile>, String> {
+    table.into_iter()
+        .map(|(pattern, entry)| {
+            let entry = ProfileEntry::deserialize(entry).map_err(|e| format!("profiles.\"{}\": {}", pattern, e))?;
+            let pattern = Regex::new(&pattern).map_err(|e| format!("profiles: invalid pattern: {}", e))?;
+            let control = match split_controls(&entry.control, 0)?.as_slice() {
+                [control] => control.clone(),
+                _ => return Err(format!("profiles.\"{}\": expected a single control", pattern)),
+            };
+            Ok(Profile { pattern, device: entry.device, control })
+        })
+        .collect()
+}
+
+fn given_on_command_line(matches: &ArgMatches, ids: &[&str]) -> bool {
+    ids.iter().any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_keep_the_order_of_the_file() {
+        let config: ConfigFile = toml::from_str(r#"
+            [profiles]
+            "^zoom$" = { device = "hw:1", control = "Mic" }
+            "discord" = { device = "default", control = "Capture" }
+            "." = { device = "hw:0", control = "Capture" }
+        "#).unwrap();
+        let profiles = parse_profiles(config.profiles.unwrap()).unwrap();
+        let patterns = profiles.iter().map(|profile| profile.pattern.as_str()).collect::<Vec<_>>();
+        assert_eq!(patterns, ["^zoom$", "discord", "."]);
+        assert_eq!(profiles[0].control, ControlId::new("Mic", 0));
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        let config: ConfigFile = toml::from_str(r#"profiles = { "(" = { device = "hw:1", control = "Mic" } }"#).unwrap();
+        assert!(parse_profiles(config.profiles.unwrap()).is_err_and(|e| e.starts_with("profiles: invalid pattern")));
+        let config: ConfigFile = toml::from_str(r#"profiles = { "zoom" = { device = "hw:1" } }"#).unwrap();
+        assert!(parse_profiles(config.profiles.unwrap()).is_err());
+    }
+
+    #[test]
+    fn rejects_an_open_mic_reminder_of_zero() {
+        let config: ConfigFile = toml::from_str("open_mic_reminder = 0").unwrap();
+        assert_eq!(config.validate(), Err("open_mic_reminder: expected at least 1".into()));
+        let config: ConfigFile = toml::from_str("open_mic_reminder = 300").unwrap();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_reminder_sound_without_reminder() {
+        let config: ConfigFile = toml::from_str(r#"reminder_sound = "reminder.wav""#).unwrap();
+        assert_eq!(config.validate(), Err("reminder_sound needs open_mic_reminder".into()));
+        let config: ConfigFile = toml::from_str("open_mic_reminder = 300\nreminder_sound = \"reminder.wav\"").unwrap();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[cfg(feature = "led")]
+    #[test]
+    fn rejects_led_indexes_past_7() {
+        let config: ConfigFile = toml::from_str("led_on = 8").unwrap();
+        assert_eq!(config.validate(), Err("led_on: expected an LED index 0-7".into()));
+        let config: ConfigFile = toml::from_str("led_off = 7").unwrap();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn splits_controls_at_commas() {
+        assert_eq!(split_controls("Capture", 0), Ok(vec![ControlId::new("Capture", 0)]));
+        assert_eq!(split_controls("Mic, Mic Boost", 0), Ok(vec![ControlId::new("Mic", 0), ControlId::new("Mic Boost", 0)]));
+        assert_eq!(split_controls("Mic,,Line,", 0), Ok(vec![ControlId::new("Mic", 0), ControlId::new("Line", 0)]));
+    }
+
+    #[test]
+    fn numbers_are_indexes_of_the_control_before() {
+        assert_eq!(split_controls("Capture,1", 0), Ok(vec![ControlId::new("Capture", 1)]));
+        assert_eq!(split_controls("Capture,1,Capture", 2), Ok(vec![ControlId::new("Capture", 1), ControlId::new("Capture", 2)]));
+        assert!(split_controls("1,Capture", 0).is_err());
+    }
+}
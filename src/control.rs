@@ -0,0 +1,178 @@
+//! The `--control-socket`, a Unix socket taking the line commands `status`, `mute`, `unmute` and `toggle` from other programs,
+//! and the requests it shares with the D-Bus service
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::capture_state::ExpectedCaptureState;
+use crate::error::PttError;
+use crate::hotkey_state::HotkeyState;
+
+/// How long a client waits for the event loop to carry out its command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Request {
+    Mute,
+    Unmute,
+    /// unmute with its own unmute delay in milliseconds
+    UnmuteAfter(u64),
+    Toggle,
+}
+
+/// The requests of all clients, carried out by the event loop so they go through the same state as the hotkeys
+pub struct Requests {
+    requests: Receiver<(Request, &'static str, Sender<bool>)>,
+    // readable while requests are waiting, so the event loop can wait for it together with the input events
+    wake_receiver: UnixStream,
+}
+
+/// Hands requests over to the event loop, from the thread of any client
+#[derive(Clone)]
+pub struct RequestSender {
+    requests: Sender<(Request, &'static str, Sender<bool>)>,
+    wake_sender: Arc<UnixStream>,
+    /// where the requests come from, for the log
+    source: &'static str,
+}
+
+pub fn channel() -> io::Result<(Requests, RequestSender)> {
+    let (wake_sender, wake_receiver) = UnixStream::pair()?;
+    wake_receiver.set_nonblocking(true)?;
+    let (request_sender, requests) = mpsc::channel();
+    Ok((Requests { requests, wake_receiver }, RequestSender { requests: request_sender, wake_sender: Arc::new(wake_sender), source: "request" }))
+}
+
+impl RequestSender {
+    /// The same channel, for requests from another source
+    pub fn with_source(&self, source: &'static str) -> RequestSender {
+        RequestSender { source, ..self.clone() }
+    }
+
+    /// Waits for the event loop to carry out the request, returning the resulting state
+    pub fn send(&self, request: Request) -> Option<bool> {
+        let (reply_sender, reply_receiver) = mpsc::channel();
+        self.requests.send((request, self.source, reply_sender)).ok()?;
+        (&*self.wake_sender).write_all(&[0]).ok()?;
+        reply_receiver.recv_timeout(REPLY_TIMEOUT).ok()
+    }
+
+    /// Wakes the event loop up without a request, e.g. to notice that it should stop
+    pub fn wake(&self) {
+        let _ = (&*self.wake_sender).write_all(&[0]);
+    }
+}
+
+/// The socket file, removed again when dropped
+pub struct SocketFile {
+    path: PathBuf,
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Creates the socket file, also for the --status-socket
+pub fn bind(path: &Path) -> Result<(UnixListener, SocketFile), PttError> {
+    let listener = match UnixListener::bind(path) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            // left behind by a process that did not get to clean up, unless someone still answers on it
+            if UnixStream::connect(path).is_ok() {
+                return Err(PttError::SocketInUse(path.to_path_buf()));
+            }
+            fs::remove_file(path).and_then(|_| UnixListener::bind(path))
+        }
+        result => result,
+    }.map_err(|source| PttError::Socket { path: path.to_path_buf(), source })?;
+    Ok((listener, SocketFile { path: path.to_path_buf() }))
+}
+
+/// Listens on the socket, serving each client in its own thread
+pub fn start(path: &Path, request_sender: RequestSender, expected_capture_state: Arc<ExpectedCaptureState>) -> Result<SocketFile, PttError> {
+    let (listener, socket_file) = bind(path)?;
+    let request_sender = request_sender.with_source("control socket");
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            match client {
+                Ok(client) => {
+                    let request_sender = request_sender.clone();
+                    let expected_capture_state = expected_capture_state.clone();
+                    thread::spawn(move || serve_client(client, &request_sender, &expected_capture_state));
+                }
+                Err(e) => warn!("Failed to accept control socket client: {}", e),
+            }
+        }
+    });
+    Ok(socket_file)
+}
+
+impl Requests {
+    /// Carries out the waiting requests, answering each with the resulting state
+    pub fn handle_requests(&self, hotkey_state: &mut HotkeyState) {
+        let mut wakeups = [0; 64];
+        while matches!((&self.wake_receiver).read(&mut wakeups), Ok(read) if read > 0) {}
+        while let Ok((request, source, reply_sender)) = self.requests.try_recv() {
+            let _ = reply_sender.send(hotkey_state.request(request, source));
+        }
+    }
+}
+
+impl AsRawFd for Requests {
+    fn as_raw_fd(&self) -> RawFd {
+        self.wake_receiver.as_raw_fd()
+    }
+}
+
+fn serve_client(client: UnixStream, request_sender: &RequestSender, expected_capture_state: &ExpectedCaptureState) {
+    debug!("Control socket client connected");
+    let Ok(reader) = client.try_clone() else {
+        return;
+    };
+    let mut writer = client;
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let request = match line.trim() {
+            "" => continue,
+            "status" => {
+                if writeln!(writer, "{}", state_name(expected_capture_state.get())).is_err() {
+                    break;
+                }
+                continue;
+            }
+            "mute" => Request::Mute,
+            "unmute" => Request::Unmute,
+            "toggle" => Request::Toggle,
+            command => {
+                if writeln!(writer, "error: unknown command {}, expected status, mute, unmute or toggle", command).is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let reply = match request_sender.send(request) {
+            Some(state) => state_name(state),
+            None => "error: no reply from the event loop",
+        };
+        if writeln!(writer, "{}", reply).is_err() {
+            break;
+        }
+    }
+    debug!("Control socket client disconnected");
+}
+
+fn state_name(state: bool) -> &'static str {
+    if state { "unmuted" } else { "muted" }
+}
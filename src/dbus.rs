@@ -0,0 +1,98 @@
+//! The `--dbus` service, so desktop widgets and scripts can control and watch the capture state without a socket of their own
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use tracing::warn;
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::fdo;
+use zbus::interface;
+use zbus::object_server::SignalEmitter;
+
+use crate::capture_state::ExpectedCaptureState;
+use crate::control::{Request, RequestSender};
+use crate::error::PttError;
+
+const NAME: &str = "io.github.xkr47.PushToTalk";
+const PATH: &str = "/io/github/xkr47/PushToTalk";
+
+static SENDER: Mutex<Option<Sender<bool>>> = Mutex::new(None);
+
+struct PushToTalk {
+    request_sender: RequestSender,
+    expected_capture_state: Arc<ExpectedCaptureState>,
+}
+
+impl PushToTalk {
+    fn send(&self, request: Request) -> fdo::Result<()> {
+        match self.request_sender.send(request) {
+            Some(_) => Ok(()),
+            None => Err(fdo::Error::Failed(String::from("No reply from the event loop"))),
+        }
+    }
+}
+
+#[interface(name = "io.github.xkr47.PushToTalk")]
+impl PushToTalk {
+    fn mute(&self) -> fdo::Result<()> {
+        self.send(Request::Mute)
+    }
+
+    fn unmute(&self) -> fdo::Result<()> {
+        self.send(Request::Unmute)
+    }
+
+    fn toggle(&self) -> fdo::Result<()> {
+        self.send(Request::Toggle)
+    }
+
+    /// true while unmuted
+    #[zbus(property)]
+    fn state(&self) -> bool {
+        self.expected_capture_state.get()
+    }
+
+    #[zbus(signal, name = "StateChanged")]
+    async fn state_changed_signal(emitter: &SignalEmitter<'_>, state: bool) -> zbus::Result<()>;
+}
+
+/// Registers the service on the session bus, and emits the states passed to `capture_state_changed` from a thread of its own
+pub fn start(request_sender: RequestSender, expected_capture_state: Arc<ExpectedCaptureState>) -> Result<(), PttError> {
+    let service = PushToTalk { request_sender: request_sender.with_source("D-Bus"), expected_capture_state };
+    let connection = Builder::session()?
+        .name(NAME)?
+        .serve_at(PATH, service)?
+        .build()?;
+    let (sender, receiver) = mpsc::channel::<bool>();
+    thread::spawn(move || {
+        while let Ok(state) = receiver.recv() {
+            if let Err(e) = emit_state_changed(&connection, state) {
+                warn!("Failed to emit D-Bus signal: {}", e);
+            }
+        }
+    });
+    *SENDER.lock().unwrap() = Some(sender);
+    Ok(())
+}
+
+/// Lets the thread end, closing the connection and so giving up the name
+pub fn stop() {
+    SENDER.lock().unwrap().take();
+}
+
+fn emit_state_changed(connection: &Connection, state: bool) -> zbus::Result<()> {
+    let service = connection.object_server().interface::<_, PushToTalk>(PATH)?;
+    let emitter = service.signal_emitter();
+    zbus::block_on(async {
+        PushToTalk::state_changed_signal(emitter, state).await?;
+        service.get().state_changed(emitter).await
+    })
+}
+
+pub fn capture_state_changed(state: bool) {
+    if let Some(sender) = SENDER.lock().unwrap().as_ref() {
+        let _ = sender.send(state);
+    }
+}
@@ -0,0 +1,46 @@
+//! The hotkey events of `--debounce`, held back until none arrived for a while, without blocking the event loop meanwhile
+
+use std::time::{Duration, Instant};
+
+use crate::hotkey_state::{HotkeyEvent, HotkeyState, KeyAction, Trigger};
+
+pub struct Debounce {
+    window: Duration,
+    burst: Vec<HotkeyEvent>,
+    quiet_at: Option<Instant>,
+}
+
+impl Debounce {
+    pub fn new(window: Duration) -> Debounce {
+        Debounce { window, burst: Vec::new(), quiet_at: None }
+    }
+
+    /// Holds back a press or release until the burst it is part of is over, or passes it on right away without --debounce
+    pub fn handle(&mut self, hotkey_state: &mut HotkeyState, pressed: bool, trigger: Trigger, action: Option<&KeyAction>, time: u32, now: Instant) {
+        if self.window.is_zero() {
+            hotkey_state.handle(pressed, trigger, action, time);
+            return;
+        }
+        self.burst.push((pressed, trigger, action.cloned(), time));
+        self.quiet_at = Some(now + self.window);
+    }
+
+    /// Passes on the burst once no event arrived for the window
+    pub fn apply_quiet(&mut self, hotkey_state: &mut HotkeyState, now: Instant) {
+        if self.quiet_at.is_some_and(|quiet_at| now >= quiet_at) {
+            self.quiet_at = None;
+            hotkey_state.handle_burst(std::mem::take(&mut self.burst));
+        }
+    }
+
+    /// Drops the burst, e.g. when the releases in it can no longer be trusted
+    pub fn discard(&mut self) {
+        self.burst.clear();
+        self.quiet_at = None;
+    }
+
+    /// How long to wait at most for the next event
+    pub fn time_left(&self, now: Instant) -> Option<Duration> {
+        self.quiet_at.map(|quiet_at| quiet_at.saturating_duration_since(now))
+    }
+}
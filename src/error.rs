@@ -0,0 +1,117 @@
+//! The ways setting up or keeping the capture state can fail
+
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+use xcb::x::{Keycode, ModMask};
+
+#[derive(Error, Debug)]
+pub enum PttError {
+    #[error(transparent)]
+    AlsaSetup(#[from] alsa::Error),
+    #[error("Failed to open alsa device {device}: {source}")]
+    AlsaOpen { device: String, source: alsa::Error },
+    #[error("Gave up reopening the alsa mixer of {device} after {attempts} attempts: {source}")]
+    AlsaRetryLimit { device: String, attempts: u32, source: Box<PttError> },
+    #[error("Failed to list sound cards: {0}")]
+    ListSoundCards(#[source] alsa::Error),
+    #[error("Failed to list mixer controls of alsa device {device}: {source}")]
+    ListControls { device: String, source: alsa::Error },
+    #[error("Could not find simple control {name} with index {index}")]
+    MixerControlNotFound { name: String, index: u32 },
+    #[error("No mixer control with a capture {method} found on alsa device {device}")]
+    NoCaptureControl { device: String, method: &'static str },
+    #[error("Several mixer controls could be used: {0}")]
+    AmbiguousControl(String),
+    #[error("Neither capture switch nor capture volume found, cannot adjust")]
+    NoCaptureSwitch,
+    #[error("Capture volume not found, cannot adjust")]
+    NoCaptureVolume,
+    #[error("No capture channels")]
+    NoCaptureChannels,
+    #[error("Mixer control {0} has no playback switch")]
+    NoPlaybackSwitch(String),
+    #[error("Failed to connect to X display \"{display}\": {source}")]
+    X11Connect { display: String, source: xcb::ConnError },
+    #[error("Could not find screen")]
+    NoScreen,
+    #[error(transparent)]
+    X11(#[from] xcb::Error),
+    #[error("Failed to {0}: {1}")]
+    X11Request(&'static str, #[source] xcb::Error),
+    #[error("Lost the connection to the X server: {0}")]
+    X11ConnectionLost(#[source] xcb::Error),
+    #[error("Gave up reconnecting to the X server after {attempts} attempts: {source}")]
+    X11ReconnectLimit { attempts: u32, source: Box<PttError> },
+    #[error("Unsupported event: {0}")]
+    UnsupportedEvent(String),
+    #[error("No window to watch for --auto-mute-on-focus-loss, WINDOWID is not set and no window has the focus")]
+    NoFocusWindow,
+    #[error("XInput2 not supported by X server, needed for {0}")]
+    NoXInput2(&'static str),
+    #[error("Failed to list input devices: {0}")]
+    ListInputDevices(#[source] Box<PttError>),
+    #[error("No keyboard matching --input-device {0} found")]
+    InputDeviceNotFound(String),
+    #[error("Unknown keysym '{keysym}'{suggestions}")]
+    UnknownKeysym { keysym: String, suggestions: String },
+    #[error("No keycode bound to keysym '{keysym}'{suggestions}")]
+    KeysymNotFound { keysym: String, suggestions: String },
+    #[error("Conflicting keybindings")]
+    ConflictingKeybindings,
+    /// the key combination is already grabbed by another application
+    #[error("Another application already uses the hotkey with keycode {keycode} and modifiers {modifiers:?}, so it cannot be grabbed")]
+    HotkeyTaken { keycode: Keycode, modifiers: ModMask },
+    #[error("{0}")]
+    HotkeyGrab(String),
+    #[error("Failed to grab the hotkeys again: {0}")]
+    Regrab(#[source] Box<PttError>),
+    #[error("Failed to keep the capture state: {0}")]
+    KeepCaptureState(#[source] Box<PttError>),
+    #[cfg(feature = "wayland")]
+    #[error("No keyboard found in /dev/input that could be opened")]
+    NoKeyboards,
+    #[cfg(feature = "wayland")]
+    #[error("{0} cannot be used with --input-backend evdev")]
+    NotSupportedByBackend(&'static str),
+    #[cfg(not(feature = "wayland"))]
+    #[error("Not built with the {0} feature")]
+    BackendNotBuilt(&'static str),
+    #[error("Failed to read sound file {}: {source}", path.display())]
+    SoundFile { path: PathBuf, source: hound::Error },
+    #[error("{0}")]
+    PidFile(String),
+    #[error("Failed to listen on socket {}: {source}", path.display())]
+    Socket { path: PathBuf, source: io::Error },
+    #[error("Socket {} is already in use by another process", .0.display())]
+    SocketInUse(PathBuf),
+    #[cfg(feature = "dbus")]
+    #[error("Failed to offer the D-Bus service: {0}")]
+    Dbus(#[from] zbus::Error),
+    #[error("Failed to write state file {}: {source}", path.display())]
+    StateFile { path: PathBuf, source: io::Error },
+    #[error("Failed to run pactl: {0}")]
+    Pactl(#[source] io::Error),
+    #[error("pactl {command} failed: {message}")]
+    PactlFailed { command: String, message: String },
+    #[error("Setup failed: {0}")]
+    Setup(String),
+    #[error("Failed to ask: {0}")]
+    Prompt(#[from] dialoguer::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// the checked requests and flushing fail with just one half of `xcb::Error`
+impl From<xcb::ProtocolError> for PttError {
+    fn from(e: xcb::ProtocolError) -> PttError {
+        PttError::X11(e.into())
+    }
+}
+
+impl From<xcb::ConnError> for PttError {
+    fn from(e: xcb::ConnError) -> PttError {
+        PttError::X11(e.into())
+    }
+}
@@ -0,0 +1,144 @@
+//! Audible cues for capture state changes, tones or WAV files, played from a separate thread so they never block the hotkeys
+
+use std::error::Error;
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use alsa::{Direction, ValueOr};
+use alsa::pcm::{Access, HwParams, IoFormat, PCM};
+use hound::{SampleFormat, WavReader};
+
+use tracing::warn;
+
+const RATE: u32 = 48000;
+const TONE_MS: u32 = 80;
+const FADE_MS: u32 = 10;
+const VOLUME: f32 = 0.25;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Cue {
+    Unmute,
+    Mute,
+    /// still unmuted after the --open-mic-reminder interval
+    Reminder,
+}
+
+impl Cue {
+    fn frequency(self) -> f32 {
+        match self {
+            Cue::Unmute => 880.0,
+            Cue::Mute => 440.0,
+            Cue::Reminder => 660.0,
+        }
+    }
+}
+
+/// A WAV file read into memory when starting, so a missing or broken file is noticed right away
+pub struct Wav {
+    channels: u16,
+    rate: u32,
+    samples: Vec<i16>,
+}
+
+impl Wav {
+    pub fn read(path: &Path) -> Result<Wav, hound::Error> {
+        let reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            SampleFormat::Int => {
+                let bits = spec.bits_per_sample as i32;
+                reader.into_samples::<i32>()
+                    .map(|sample| sample.map(|value| (if bits > 16 { value >> (bits - 16) } else { value << (16 - bits) }) as i16))
+                    .collect::<Result<_, _>>()?
+            }
+            SampleFormat::Float => reader.into_samples::<f32>()
+                .map(|sample| sample.map(|value| (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Wav { channels: spec.channels, rate: spec.sample_rate, samples })
+    }
+}
+
+static SENDER: Mutex<Option<Sender<Cue>>> = Mutex::new(None);
+
+/// Starts playing the cues passed to `play` on the given playback device, the WAV files if given and otherwise the tones if `beep`;
+/// reminders are only sent when asked for, so they always play
+pub fn start(device: String, beep: bool, unmute_wav: Option<Wav>, mute_wav: Option<Wav>, reminder_wav: Option<Wav>) {
+    let (sender, receiver) = mpsc::channel::<Cue>();
+    thread::spawn(move || {
+        while let Ok(mut cue) = receiver.recv() {
+            // don't fall behind on rapid changes, only the latest state matters
+            while let Ok(later_cue) = receiver.try_recv() {
+                cue = later_cue;
+            }
+            let wav = match cue {
+                Cue::Unmute => unmute_wav.as_ref(),
+                Cue::Mute => mute_wav.as_ref(),
+                Cue::Reminder => reminder_wav.as_ref(),
+            };
+            let result = match wav {
+                Some(wav) => play_wav(&device, wav),
+                None if beep || matches!(cue, Cue::Reminder) => play_tone(&device, cue.frequency()),
+                None => Ok(()),
+            };
+            if let Err(e) = result {
+                warn!("Failed to play {:?} cue: {}", cue, e);
+            }
+        }
+    });
+    *SENDER.lock().unwrap() = Some(sender);
+}
+
+/// Lets the thread end once it is done with what was sent so far
+pub fn stop() {
+    SENDER.lock().unwrap().take();
+}
+
+pub fn play(cue: Cue) {
+    if let Some(sender) = SENDER.lock().unwrap().as_ref() {
+        let _ = sender.send(cue);
+    }
+}
+
+fn open_pcm(device: &str, channels: u16, rate: u32) -> Result<PCM, alsa::Error> {
+    let pcm = PCM::new(device, Direction::Playback, false)?;
+    {
+        let hw_params = HwParams::any(&pcm)?;
+        hw_params.set_channels(channels as u32)?;
+        hw_params.set_rate(rate, ValueOr::Nearest)?;
+        hw_params.set_format(<i16 as IoFormat>::FORMAT)?;
+        hw_params.set_access(Access::RWInterleaved)?;
+        pcm.hw_params(&hw_params)?;
+    }
+    Ok(pcm)
+}
+
+fn play_wav(device: &str, wav: &Wav) -> Result<(), Box<dyn Error>> {
+    let pcm = open_pcm(device, wav.channels, wav.rate)?;
+    pcm.io_i16()?.writei(&wav.samples)?;
+    pcm.drain()?;
+    Ok(())
+}
+
+fn play_tone(device: &str, frequency: f32) -> Result<(), Box<dyn Error>> {
+    let pcm = open_pcm(device, 1, RATE)?;
+    let rate = pcm.hw_params_current()?.get_rate()?;
+
+    let length = (rate * TONE_MS / 1000) as usize;
+    let fade = (rate * FADE_MS / 1000) as usize;
+    let samples = (0..length)
+        .map(|i| {
+            // fade in and out to avoid clicks
+            let envelope = i.min(length - 1 - i).min(fade) as f32 / fade as f32;
+            let value = (2.0 * PI * frequency * i as f32 / rate as f32).sin() * envelope * VOLUME;
+            (value * i16::MAX as f32) as i16
+        })
+        .collect::<Vec<_>>();
+
+    pcm.io_i16()?.writei(&samples)?;
+    pcm.drain()?;
+    Ok(())
+}
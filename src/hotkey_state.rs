@@ -0,0 +1,673 @@
+//! What the hotkeys do to the capture state, the same for every input backend
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+use xcb::x::Keycode;
+
+use crate::capture_state::ExpectedCaptureState;
+use crate::control::Request;
+use crate::pending_change::PendingChange;
+use crate::{feedback, ipc, latency, state_file, state_reporter, Mode, ResumeState};
+#[cfg(feature = "dbus")]
+use crate::dbus;
+#[cfg(feature = "led")]
+use crate::led;
+#[cfg(feature = "notifications")]
+use crate::notifications;
+
+#[derive(Debug, Clone)]
+pub enum KeyAction {
+    Push,
+    Toggle,
+    Unmute,
+    Mute,
+    Panic,
+}
+
+/// A key or button that can be bound to an action, so several push bindings can be held at once
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Key(Keycode),
+    Button(u32),
+}
+
+/// A press or release of a trigger, with the action it is bound to if any, and the time of the event
+pub type HotkeyEvent = (bool, Trigger, Option<KeyAction>, u32);
+
+pub struct HotkeyState {
+    expected_capture_state: Arc<ExpectedCaptureState>,
+    unmute_delay_ms: u64,
+    remute_delay_ms: u64,
+    mode: Mode,
+    invert: bool,
+    tap_toggle_threshold_ms: u32,
+    double_tap_lock_ms: u32,
+    min_hold_ms: u32,
+    max_unmute: Option<Duration>,
+    open_mic_reminder: Option<Duration>,
+    auto_mute_idle: Option<Duration>,
+
+    // don't immediately unmute on release after muting on press
+    mute_pending_release: bool,
+
+    // push hotkey tapped or double-tapped, so behave as if it was still held until the next press is released
+    push_latched: bool,
+    push_press_unlatches: bool,
+    push_press_time: u32,
+    previous_push_press_time: Option<u32>,
+
+    // cleared by the panic key
+    armed: bool,
+
+    // pressed push bindings; with several of them the mic stays open until the last one is released
+    held_push_triggers: HashSet<Trigger>,
+
+    // an unmute waiting for --unmute-delay, or a mute waiting for --remute-delay
+    pending_change: PendingChange,
+
+    // when the microphone was last seen opening, for --max-unmute-ms
+    unmuted_since: Option<Instant>,
+
+    // when to play the next --open-mic-reminder, while unmuted
+    next_reminder: Option<Instant>,
+
+    // when a hotkey was last pressed or a request arrived, for --auto-mute-idle
+    last_activity: Instant,
+}
+
+impl HotkeyState {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(expected_capture_state: Arc<ExpectedCaptureState>, unmute_delay_ms: u64, remute_delay_ms: u64, mode: Mode, invert: bool, tap_toggle_threshold_ms: u32, double_tap_lock_ms: u32, min_hold_ms: u32, max_unmute: Option<Duration>, open_mic_reminder: Option<Duration>, auto_mute_idle: Option<Duration>) -> HotkeyState {
+        HotkeyState {
+            expected_capture_state,
+            unmute_delay_ms,
+            remute_delay_ms,
+            mode,
+            invert,
+            tap_toggle_threshold_ms,
+            double_tap_lock_ms,
+            min_hold_ms,
+            max_unmute,
+            open_mic_reminder,
+            auto_mute_idle,
+            mute_pending_release: false,
+            push_latched: false,
+            push_press_unlatches: false,
+            push_press_time: 0,
+            previous_push_press_time: None,
+            armed: true,
+            held_push_triggers: HashSet::new(),
+            pending_change: PendingChange::default(),
+            unmuted_since: None,
+            next_reminder: None,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// The capture state once the delayed change is done
+    pub fn target_state(&self) -> bool {
+        target_state(&self.expected_capture_state, &self.pending_change)
+    }
+
+    /// Carries out the delayed change once it is due, mutes once unmuted for longer than --max-unmute-ms and plays the --open-mic-reminder
+    pub fn apply_due_change(&mut self, now: Instant) {
+        apply_due_change(&self.expected_capture_state, &mut self.pending_change, now);
+        self.unmuted_since = if self.expected_capture_state.get() { self.unmuted_since.or(Some(now)) } else { None };
+        self.next_reminder = self.open_mic_reminder.zip(self.unmuted_since)
+            .map(|(interval, unmuted_since)| self.next_reminder.unwrap_or(unmuted_since + interval));
+        if let (Some(interval), Some(next_reminder)) = (self.open_mic_reminder, self.next_reminder) {
+            if now >= next_reminder {
+                info!("Microphone open for {:?}", now.duration_since(self.unmuted_since.unwrap_or(now)));
+                feedback::play(feedback::Cue::Reminder);
+                // not catching up on the missed ones after a suspend
+                self.next_reminder = Some(now + interval);
+            }
+        }
+        if let (Some(max_unmute), Some(unmuted_since)) = (self.max_unmute, self.unmuted_since) {
+            if now.duration_since(unmuted_since) >= max_unmute {
+                warn!("Max talk time of {:?} reached, muting in case a key got stuck or the microphone was left on", max_unmute);
+                // also ends a tap or double-tap lock, and a held key only mutes again when released
+                self.release_all();
+                // also plays the mute cue, so it does not go unnoticed
+                self.mute();
+                self.unmuted_since = None;
+                self.next_reminder = None;
+            }
+        }
+        if let Some(idle) = self.auto_mute_idle {
+            if self.left_open() && now.saturating_duration_since(self.last_activity) >= idle {
+                info!("No hotkey pressed for {:?}, muting", idle);
+                self.release_all();
+                self.mute();
+            }
+        }
+    }
+
+    /// How long until the delayed change, the --max-unmute-ms timeout, the next --open-mic-reminder or the --auto-mute-idle timeout is due
+    pub fn time_left(&self, now: Instant) -> Option<Duration> {
+        let timeout = self.max_unmute.zip(self.unmuted_since).map(|(max_unmute, unmuted_since)| (unmuted_since + max_unmute).saturating_duration_since(now));
+        let reminder = self.next_reminder.map(|next_reminder| next_reminder.saturating_duration_since(now));
+        let idle = self.auto_mute_idle.filter(|_| self.left_open()).map(|idle| (self.last_activity + idle).saturating_duration_since(now));
+        [self.pending_change.time_left(now), timeout, reminder, idle].into_iter().flatten().min()
+    }
+
+    /// Unmuted without holding the push hotkey, by toggling or locking it open, which --auto-mute-idle ends
+    fn left_open(&self) -> bool {
+        (self.mode == Mode::Toggle || self.push_latched) && self.target_state()
+    }
+
+    /// Forgets the held keys and any lock, e.g. when their releases can no longer arrive
+    fn release_all(&mut self) {
+        self.mute_pending_release = false;
+        self.push_latched = false;
+        self.pending_change.cancel();
+        self.held_push_triggers.clear();
+    }
+
+    /// The push keys holding the microphone open, for checking that they are still down; none during the unmute or remute delay
+    pub fn held_push_keys(&self) -> Vec<Keycode> {
+        if self.mode != Mode::Push || self.invert || !self.expected_capture_state.get() || self.pending_change.state().is_some() {
+            return Vec::new();
+        }
+        self.held_push_triggers.iter()
+            .filter_map(|trigger| match trigger {
+                Trigger::Key(keycode) => Some(*keycode),
+                Trigger::Button(_) => None,
+            })
+            .collect()
+    }
+
+    /// Forgets a push key that is no longer down although its release never arrived, muting unless other push keys are still held
+    pub fn stuck_key_released(&mut self, keycode: Keycode) {
+        warn!("Push key {} is no longer down but its release got lost", keycode);
+        self.held_push_triggers.remove(&Trigger::Key(keycode));
+        if self.held_push_triggers.is_empty() && self.target_state() {
+            info!("Muting after the lost push-release");
+            self.release_all();
+            self.mute();
+        }
+    }
+
+    /// Mutes right away for --auto-mute-on-focus-loss, forgetting the held keys so the next press unmutes again
+    pub fn focus_lost(&mut self) {
+        if self.target_state() {
+            info!("Muting as the focus moved to another window");
+            self.release_all();
+            self.mute();
+        }
+    }
+
+    /// Starts over after a suspend, in the state asked for by --resume-state
+    pub fn resumed(&mut self, resume_state: ResumeState) {
+        self.release_all();
+        match resume_state {
+            ResumeState::Muted if self.target_state() => {
+                info!("Muting after resuming");
+                self.mute();
+            }
+            ResumeState::Unmuted if self.armed && !self.target_state() => {
+                info!("Unmuting after resuming");
+                self.unmute();
+            }
+            _ => (),
+        }
+    }
+
+    /// Nobody can release the push hotkey while the hotkeys cannot be watched, so the microphone stays muted until then
+    pub fn disconnected(&mut self) {
+        self.release_all();
+        if self.expected_capture_state.get() {
+            info!("Muting while disconnected");
+            self.mute();
+        }
+    }
+
+    /// Back to the released state of the push hotkey once the hotkeys can be watched again
+    pub fn reconnected(&mut self) {
+        if self.invert && self.armed {
+            info!("Unmuting after reconnecting");
+            self.unmute();
+        }
+    }
+
+    /// Carries out a request from the --control-socket, D-Bus or a signal like the mute and unmute keys, returning the resulting state
+    pub fn request(&mut self, request: Request, source: &str) -> bool {
+        self.last_activity = Instant::now();
+        // while disarmed by the panic key, only the panic key does anything
+        if self.armed {
+            let unmuting = match request {
+                Request::Mute => false,
+                Request::Unmute | Request::UnmuteAfter(_) => true,
+                Request::Toggle => !self.target_state(),
+            };
+            self.push_latched = false;
+            if unmuting && !self.target_state() {
+                info!("Unmuting by {}", source);
+                let unmute_delay_ms = match request {
+                    Request::UnmuteAfter(unmute_delay_ms) => unmute_delay_ms,
+                    _ => self.unmute_delay_ms,
+                };
+                unmute(&self.expected_capture_state, &mut self.pending_change, unmute_delay_ms);
+            } else if !unmuting && self.target_state() {
+                info!("Muting by {}", source);
+                self.mute();
+            }
+        }
+        self.target_state()
+    }
+
+    fn mute(&mut self) {
+        mute(&self.expected_capture_state, &mut self.pending_change);
+    }
+
+    fn unmute(&mut self) {
+        unmute(&self.expected_capture_state, &mut self.pending_change, self.unmute_delay_ms);
+    }
+
+    /// Reacts to a burst of presses and releases from --debounce once it is over, once for each trigger that ended up in the other
+    /// state, as of its first event; a trigger back in the state it started in did not change at all
+    pub fn handle_burst(&mut self, burst: Vec<HotkeyEvent>) {
+        // the first event of each trigger, with the state it ended in and the action of its last event
+        let mut changes: Vec<(HotkeyEvent, bool)> = Vec::new();
+        for (pressed, trigger, action, time) in burst {
+            match changes.iter_mut().find(|((_, changed, _, _), _)| *changed == trigger) {
+                Some(((_, _, last_action, _), last_pressed)) => {
+                    *last_pressed = pressed;
+                    *last_action = action;
+                }
+                None => changes.push(((pressed, trigger, action, time), pressed)),
+            }
+        }
+        for ((pressed, trigger, action, time), last_pressed) in changes {
+            if pressed != last_pressed {
+                debug!("Ignoring the bouncing of {:?}", trigger);
+                continue;
+            }
+            self.handle(pressed, trigger, action.as_ref(), time);
+        }
+    }
+
+    /// Reacts to a press or release of a key or button, with the action it is bound to if any
+    pub fn handle(&mut self, pressed: bool, trigger: Trigger, action: Option<&KeyAction>, time: u32) {
+        if action.is_some() {
+            latency::key_event(time);
+        }
+        if pressed {
+            self.last_activity = Instant::now();
+        }
+        let (mode, invert) = (self.mode, self.invert);
+        // while disarmed by the panic key, only the panic key does anything
+        let action = match action {
+            Some(KeyAction::Panic) if pressed => {
+                if self.armed {
+                    info!("PANIC MUTE, hotkeys disarmed until the panic key is pressed again");
+                    self.mute();
+                    self.mute_pending_release = false;
+                    self.push_latched = false;
+                    self.push_press_unlatches = false;
+                } else {
+                    info!("Re-armed");
+                    if invert && mode == Mode::Push {
+                        self.unmute();
+                    }
+                }
+                self.armed = !self.armed;
+                return;
+            }
+            Some(KeyAction::Panic) => return,
+            _ if !self.armed => None,
+            action => action,
+        };
+        if pressed {
+            if let Some(KeyAction::Push) = action {
+                self.held_push_triggers.insert(trigger);
+            }
+            match action {
+                Some(KeyAction::Push) if self.held_push_triggers.len() > 1 => (),
+                Some(KeyAction::Push) if mode == Mode::Push => {
+                    let double_tapped = !self.push_latched && self.double_tap_lock_ms > 0
+                        && self.previous_push_press_time.is_some_and(|previous| time.wrapping_sub(previous) < self.double_tap_lock_ms);
+                    self.push_press_unlatches = self.push_latched;
+                    self.push_press_time = time;
+                    self.previous_push_press_time = if self.push_latched || double_tapped { None } else { Some(time) };
+                    if self.push_latched {
+                        // already in the pressed state
+                    } else if !invert && self.pending_change.state() == Some(false) {
+                        info!("Pushed again during the remute delay, staying unmuted");
+                        self.pending_change.cancel();
+                    } else if invert {
+                        info!("Muting by push-press");
+                        self.mute();
+                    } else {
+                        info!("Unmuting by push-press");
+                        // not before --min-hold either, which the release checks by the event times
+                        let delay_ms = self.unmute_delay_ms.max(self.min_hold_ms.into());
+                        unmute(&self.expected_capture_state, &mut self.pending_change, delay_ms);
+                    }
+                    if double_tapped {
+                        info!("Locked {}", if invert { "muted" } else { "open" });
+                        self.push_latched = true;
+                    }
+                },
+                Some(KeyAction::Push) => {
+                    if self.target_state() {
+                        info!("Toggled to muted");
+                        self.mute();
+                    } else {
+                        info!("Toggled to unmuted");
+                        self.unmute();
+                    }
+                },
+                Some(KeyAction::Toggle) => {
+                    if self.target_state() {
+                        info!("Muting by toggle-press");
+                        self.mute();
+                        self.mute_pending_release = true;
+                        self.push_latched = false;
+                    }
+                },
+                // explicit keys also end a tap or double-tap lock, their releases are ignored
+                Some(KeyAction::Unmute) => {
+                    self.push_latched = false;
+                    if !self.target_state() {
+                        info!("Unmuting by unmute-key");
+                        self.unmute();
+                    }
+                },
+                Some(KeyAction::Mute) => {
+                    self.push_latched = false;
+                    if self.target_state() {
+                        info!("Muting by mute-key");
+                        self.mute();
+                    }
+                },
+                _ => ()
+            }
+        } else {
+            // forget the key even if its modifiers were released first and the release does not match the hotkey
+            self.held_push_triggers.remove(&trigger);
+
+            match action {
+                Some(KeyAction::Push) if !self.held_push_triggers.is_empty() => (),
+                // in toggle mode the state only changes on press
+                Some(KeyAction::Push) if mode == Mode::Push => {
+                    let release = if self.push_press_unlatches {
+                        info!("Unlocked");
+                        self.push_press_unlatches = false;
+                        self.push_latched = false;
+                        true
+                    } else if self.push_latched {
+                        // locked by a double tap
+                        false
+                    } else if time.wrapping_sub(self.push_press_time) < self.tap_toggle_threshold_ms {
+                        info!("Push tapped, keeping {}", if invert { "muted" } else { "unmuted" });
+                        self.push_latched = true;
+                        false
+                    } else {
+                        true
+                    };
+                    if !release {
+                        // stays in the pressed state
+                    } else if invert {
+                        info!("Unmuting by push-release");
+                        self.unmute();
+                    } else if !self.target_state() {
+                        // muted meanwhile, e.g. by --max-unmute-ms
+                    } else if time.wrapping_sub(self.push_press_time) < self.min_hold_ms && !self.expected_capture_state.get() {
+                        info!("Released before the minimum hold time, staying muted");
+                        self.pending_change.cancel();
+                    } else if self.pending_change.state() == Some(true) {
+                        // a quick tap never opens the microphone
+                        info!("Released within the unmute delay, staying muted");
+                        self.pending_change.cancel();
+                    } else {
+                        info!("Muting by push-release");
+                        mute_after(&self.expected_capture_state, &mut self.pending_change, self.remute_delay_ms);
+                    }
+                },
+                Some(KeyAction::Toggle) => {
+                    if self.mute_pending_release {
+                        self.mute_pending_release = false;
+                    } else if !self.target_state() {
+                        info!("Unmuting by toggle-release");
+                        self.unmute();
+                    }
+                },
+                _ => ()
+            }
+        }
+    }
+}
+
+/// Mutes right away, instead of any delayed change
+pub fn mute(expected_capture_state: &ExpectedCaptureState, pending_change: &mut PendingChange) {
+    pending_change.cancel();
+    set_expected_capture_state(expected_capture_state, false);
+    // only once muting, the enforce thread has set the mixer long before the tone starts, so it is not captured
+    feedback::play(feedback::Cue::Mute);
+}
+
+/// Mutes once the delay has passed, unless cancelled meanwhile
+fn mute_after(expected_capture_state: &ExpectedCaptureState, pending_change: &mut PendingChange, remute_delay_ms: u64) {
+    if remute_delay_ms == 0 {
+        mute(expected_capture_state, pending_change);
+    } else {
+        pending_change.schedule(false, Duration::from_millis(remute_delay_ms));
+    }
+}
+
+/// Unmutes once the delay has passed, unless cancelled meanwhile; the event loop goes on in the meantime
+pub fn unmute(expected_capture_state: &ExpectedCaptureState, pending_change: &mut PendingChange, unmute_delay_ms: u64) {
+    if expected_capture_state.get() {
+        // only a delayed mute to cancel
+        pending_change.cancel();
+        return;
+    }
+    if pending_change.state() == Some(true) {
+        return;
+    }
+    // during the unmute delay, so the cue is mostly over before the microphone opens
+    feedback::play(feedback::Cue::Unmute);
+    if unmute_delay_ms == 0 {
+        pending_change.cancel();
+        set_expected_capture_state(expected_capture_state, true);
+    } else {
+        pending_change.schedule(true, Duration::from_millis(unmute_delay_ms));
+    }
+}
+
+/// Carries out the delayed change once it is due
+pub fn apply_due_change(expected_capture_state: &ExpectedCaptureState, pending_change: &mut PendingChange, now: Instant) {
+    match pending_change.take_due(now) {
+        Some(true) => set_expected_capture_state(expected_capture_state, true),
+        Some(false) => mute(expected_capture_state, pending_change),
+        None => (),
+    }
+}
+
+/// The capture state once the delayed change is done
+fn target_state(expected_capture_state: &ExpectedCaptureState, pending_change: &PendingChange) -> bool {
+    pending_change.state().unwrap_or_else(|| expected_capture_state.get())
+}
+
+/// Hands the state over to the enforce thread, which sets it on the mixer
+fn set_expected_capture_state(expected_capture_state: &ExpectedCaptureState, state: bool) {
+    // before waking up the enforce threads, so they find the change to time
+    if expected_capture_state.get() != state {
+        latency::state_requested(state);
+    }
+    if expected_capture_state.set(state) {
+        state_reporter::capture_state_changed(state);
+        ipc::capture_state_changed(state);
+        state_file::capture_state_changed(state);
+        #[cfg(feature = "notifications")]
+        notifications::capture_state_changed(state);
+        #[cfg(feature = "led")]
+        led::capture_state_changed(state);
+        #[cfg(feature = "dbus")]
+        dbus::capture_state_changed(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNMUTE_DELAY_MS: u64 = 150;
+
+    const MUTE_DELAY_MS: u64 = 300;
+
+    fn push_state(expected_capture_state: &Arc<ExpectedCaptureState>) -> HotkeyState {
+        HotkeyState::new(expected_capture_state.clone(), UNMUTE_DELAY_MS, 0, Mode::Push, false, 0, 0, 0, None, None, None)
+    }
+
+    #[test]
+    fn tap_shorter_than_unmute_delay_never_unmutes() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = push_state(&expected);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        state.handle(false, Trigger::Key(62), Some(&KeyAction::Push), 1050);
+        assert_eq!(state.time_left(Instant::now()), None);
+        state.apply_due_change(Instant::now() + Duration::from_secs(1));
+        assert!(!expected.get());
+    }
+
+    #[test]
+    fn held_past_unmute_delay_unmutes_until_release() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = push_state(&expected);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        state.apply_due_change(Instant::now() + Duration::from_secs(1));
+        assert!(expected.get());
+        state.handle(false, Trigger::Key(62), Some(&KeyAction::Push), 2000);
+        assert!(!expected.get());
+    }
+
+    #[test]
+    fn press_during_mute_delay_stays_unmuted_without_unmute_delay() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), UNMUTE_DELAY_MS, MUTE_DELAY_MS, Mode::Push, false, 0, 0, 0, None, None, None);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        state.apply_due_change(Instant::now() + Duration::from_secs(1));
+        state.handle(false, Trigger::Key(62), Some(&KeyAction::Push), 2000);
+        assert!(expected.get());
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 2100);
+        assert!(expected.get());
+        assert_eq!(state.time_left(Instant::now()), None);
+    }
+
+    #[test]
+    fn release_before_min_hold_never_unmutes() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), UNMUTE_DELAY_MS, 0, Mode::Push, false, 0, 0, 400, None, None, None);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        // past the unmute delay, but still waiting for the minimum hold time
+        state.apply_due_change(Instant::now() + Duration::from_millis(UNMUTE_DELAY_MS + 50));
+        assert!(!expected.get());
+        state.handle(false, Trigger::Key(62), Some(&KeyAction::Push), 1300);
+        assert_eq!(state.time_left(Instant::now()), None);
+        assert!(!expected.get());
+    }
+
+    #[test]
+    fn max_unmute_mutes_a_stuck_toggle() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), 0, 0, Mode::Toggle, false, 0, 0, 0, Some(Duration::from_secs(60)), None, None);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        let now = Instant::now();
+        state.apply_due_change(now);
+        assert!(expected.get());
+        assert_eq!(state.time_left(now), Some(Duration::from_secs(60)));
+        state.apply_due_change(now + Duration::from_secs(60));
+        assert!(!expected.get());
+        assert_eq!(state.time_left(now), None);
+    }
+
+    #[test]
+    fn stuck_key_checked_only_after_unmute_delay() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = push_state(&expected);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        assert_eq!(state.held_push_keys(), Vec::<Keycode>::new());
+        state.apply_due_change(Instant::now() + Duration::from_secs(1));
+        assert_eq!(state.held_push_keys(), [62]);
+        state.stuck_key_released(62);
+        assert!(!expected.get());
+        // the release arriving after all does not unmute again
+        state.handle(false, Trigger::Key(62), Some(&KeyAction::Push), 2000);
+        assert!(!expected.get());
+    }
+
+    #[test]
+    fn open_mic_reminder_repeats_until_muted() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), 0, 0, Mode::Toggle, false, 0, 0, 0, None, Some(Duration::from_secs(30)), None);
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        let now = Instant::now();
+        state.apply_due_change(now);
+        assert_eq!(state.time_left(now), Some(Duration::from_secs(30)));
+        let later = now + Duration::from_secs(30);
+        state.apply_due_change(later);
+        assert!(expected.get());
+        assert_eq!(state.time_left(later), Some(Duration::from_secs(30)));
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 2000);
+        state.apply_due_change(later);
+        assert_eq!(state.time_left(later), None);
+    }
+
+    #[test]
+    fn bouncing_press_unmutes_once() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), 0, 0, Mode::Push, false, 0, 0, 0, None, None, None);
+        state.handle_burst(vec![
+            (true, Trigger::Key(62), Some(KeyAction::Push), 1000),
+            (false, Trigger::Key(62), Some(KeyAction::Push), 1002),
+            (true, Trigger::Key(62), Some(KeyAction::Push), 1004),
+        ]);
+        assert!(expected.get());
+        assert_eq!(state.held_push_keys(), [62]);
+        // a release bouncing back to held is no release
+        state.handle_burst(vec![
+            (false, Trigger::Key(62), Some(KeyAction::Push), 2000),
+            (true, Trigger::Key(62), Some(KeyAction::Push), 2003),
+        ]);
+        assert!(expected.get());
+        state.handle_burst(vec![
+            (false, Trigger::Key(62), Some(KeyAction::Push), 3000),
+            (true, Trigger::Key(62), Some(KeyAction::Push), 3001),
+            (false, Trigger::Key(62), Some(KeyAction::Push), 3003),
+        ]);
+        assert!(!expected.get());
+    }
+
+    #[test]
+    fn bouncing_toggle_press_toggles_once() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), 0, 0, Mode::Toggle, false, 0, 0, 0, None, None, None);
+        state.handle_burst(vec![
+            (true, Trigger::Key(62), Some(KeyAction::Push), 1000),
+            (false, Trigger::Key(62), Some(KeyAction::Push), 1001),
+            (true, Trigger::Key(62), Some(KeyAction::Push), 1003),
+            (false, Trigger::Key(62), Some(KeyAction::Push), 1004),
+            (true, Trigger::Key(62), Some(KeyAction::Push), 1006),
+        ]);
+        assert!(expected.get());
+    }
+
+    #[test]
+    fn auto_mute_idle_mutes_a_forgotten_toggle() {
+        let expected = Arc::new(ExpectedCaptureState::new(false, 1).unwrap());
+        let mut state = HotkeyState::new(expected.clone(), 0, 0, Mode::Toggle, false, 0, 0, 0, None, None, Some(Duration::from_secs(120)));
+        state.handle(true, Trigger::Key(62), Some(&KeyAction::Push), 1000);
+        let now = Instant::now();
+        state.apply_due_change(now);
+        assert!(expected.get());
+        assert!(state.time_left(now).is_some_and(|left| left <= Duration::from_secs(120)));
+        state.apply_due_change(now + Duration::from_secs(120));
+        assert!(!expected.get());
+        assert_eq!(state.time_left(now), None);
+    }
+}
@@ -0,0 +1,4 @@
+//! Input backends besides X11, which watch the hotkeys without an X server
+
+#[cfg(feature = "wayland")]
+pub mod evdev;
@@ -0,0 +1,229 @@
+//! Watches the hotkeys on the keyboards themselves through libinput, for Wayland where X clients only get the keys sent to their own windows
+//!
+//! Reading /dev/input/event* needs the user to be in the input group, or a udev rule giving access to the keyboards.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use input::{Device, DeviceCapability, Event, Libinput, LibinputInterface};
+use input::event::{DeviceEvent, EventTrait, KeyboardEvent};
+use input::event::keyboard::{KeyState, KeyboardEventTrait};
+use tracing::{debug, info};
+use xcb::x::{Keycode, ModMask};
+
+use crate::control::Requests;
+use crate::debounce::Debounce;
+use crate::error::PttError;
+use crate::hotkey_state::{HotkeyState, KeyAction, Trigger};
+use crate::{hotkey_entries, key_grabs, modifier_combinations, notify_ready, ping_watchdog, poll_with_timeout, raw_key_state, suspended_time, try_collect_map, Config, HotkeyKey, Shutdown, RESUME_CHECK_INTERVAL};
+
+const INPUT_DIR: &str = "/dev/input";
+
+/// How often to look for keyboards plugged in meanwhile, since the path backend of libinput does not notice them
+const DEVICE_SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// evdev key codes are the X keycodes of the usual xkb keymaps minus 8
+const EVDEV_OFFSET: u32 = 8;
+
+/// The modifier keys of the usual xkb keymaps by X keycode, since without an X server there is no modifier mapping to ask for
+const MODIFIER_KEYS: [(Keycode, ModMask); 11] = [
+    (50, ModMask::SHIFT),    // Shift_L
+    (62, ModMask::SHIFT),    // Shift_R
+    (66, ModMask::LOCK),     // Caps_Lock
+    (37, ModMask::CONTROL),  // Control_L
+    (105, ModMask::CONTROL), // Control_R
+    (64, ModMask::N1),       // Alt_L
+    (77, ModMask::N2),       // Num_Lock
+    (133, ModMask::N4),      // Super_L
+    (134, ModMask::N4),      // Super_R
+    (108, ModMask::N5),      // ISO_Level3_Shift
+    (92, ModMask::N5),       // ISO_Level3_Shift on some keyboards
+];
+
+/// Opens the devices for libinput with the permissions of the user
+struct Interface;
+
+impl LibinputInterface for Interface {
+    fn open_restricted(&mut self, path: &Path, flags: i32) -> Result<OwnedFd, i32> {
+        let access = flags & libc::O_ACCMODE;
+        OpenOptions::new()
+            .custom_flags(flags)
+            .read(access == libc::O_RDONLY || access == libc::O_RDWR)
+            .write(access == libc::O_WRONLY || access == libc::O_RDWR)
+            .open(path)
+            .map(OwnedFd::from)
+            .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))
+    }
+
+    fn close_restricted(&mut self, fd: OwnedFd) {
+        drop(File::from(fd));
+    }
+}
+
+/// Watches the keyboards until exiting, returning only if the setup fails
+pub fn listen(shutdown: &Shutdown, hotkey_state: &mut HotkeyState, debounce: &mut Debounce, requests: &Requests, config: &Config) -> Result<(), PttError> {
+    let input_device = config.input_device.as_deref();
+    // keysyms depend on the keymap of the compositor, which is not known here
+    if config.push_keysym.is_some() || config.toggle_keysym.is_some() {
+        return Err(PttError::NotSupportedByBackend("keysyms"));
+    }
+    if config.push_button.is_some() {
+        return Err(PttError::NotSupportedByBackend("--push-button"));
+    }
+
+    let modifier_mapping = HashMap::from(MODIFIER_KEYS);
+    let own_modifier = |keycode: &Keycode| modifier_mapping.get(keycode).cloned().unwrap_or_else(ModMask::empty);
+
+    // keycode 0 disables the hotkey
+    let mut bindings = vec![
+        (config.push_keycode.iter().copied().filter(|keycode| *keycode != 0).collect::<Vec<_>>(), config.push_modifiers, KeyAction::Push),
+        (config.toggle_keycode.iter().copied().filter(|keycode| *keycode != 0).collect(), config.toggle_modifiers, KeyAction::Toggle),
+    ];
+    for (hotkeys, action) in [(config.push_hotkey.as_slice(), KeyAction::Push), (config.unmute_key.as_slice(), KeyAction::Unmute), (config.mute_key.as_slice(), KeyAction::Mute), (config.panic_key.as_slice(), KeyAction::Panic)] {
+        for hotkey in hotkeys {
+            match &hotkey.key {
+                HotkeyKey::Keycode(keycode) => bindings.push((vec![*keycode], hotkey.modifiers, action.clone())),
+                HotkeyKey::Keysym(_) => return Err(PttError::NotSupportedByBackend("keysyms")),
+            }
+        }
+    }
+    // looked up like the raw key events of --passive, by the modifiers of the other held keys
+    let grabs = bindings.iter()
+        .map(|(keycodes, modifiers, action)| (key_grabs(keycodes, &modifier_combinations(*modifiers, config.strict_modifiers), own_modifier), action.clone()))
+        .collect::<Vec<_>>();
+    let hotkey_map = try_collect_map(grabs.iter().flat_map(|(grabs, action)| hotkey_entries(grabs, action.clone())))?;
+
+    let mut input = Libinput::new_from_path(Interface);
+    let mut known_paths = HashSet::new();
+    let keyboards = add_new_keyboards(&mut input, &mut known_paths)?;
+    if keyboards.is_empty() {
+        return Err(PttError::NoKeyboards);
+    }
+    if let Some(input_device) = input_device {
+        if !keyboards.iter().any(|keyboard| is_input_device(keyboard, input_device)) {
+            return Err(PttError::InputDeviceNotFound(input_device.to_string()));
+        }
+    }
+    let mut last_scan = Instant::now();
+
+    // from all keyboards, for the modifier state
+    let mut held_keys = HashSet::new();
+
+    let watchdog_interval = notify_ready(hotkey_state, !config.no_sd_notify);
+    let mut last_watchdog = Instant::now();
+
+    let mut suspended = suspended_time();
+
+    loop {
+        if let Some(result) = shutdown.stopped() {
+            return result;
+        }
+        ping_watchdog(watchdog_interval, &mut last_watchdog);
+
+        let previously_suspended = std::mem::replace(&mut suspended, suspended_time());
+        if suspended.saturating_sub(previously_suspended) >= RESUME_CHECK_INTERVAL {
+            info!("Resumed after being suspended for {:?}", suspended - previously_suspended);
+            held_keys.clear();
+            debounce.discard();
+            hotkey_state.resumed(config.resume_state);
+            shutdown.expected_capture_state.request_reopen();
+        }
+
+        if last_scan.elapsed() >= DEVICE_SCAN_INTERVAL {
+            add_new_keyboards(&mut input, &mut known_paths)?;
+            last_scan = Instant::now();
+        }
+
+        requests.handle_requests(hotkey_state);
+        debounce.apply_quiet(hotkey_state, Instant::now());
+        hotkey_state.apply_due_change(Instant::now());
+
+        // wakes up in time for the watchdog, a delayed change, the end of a --debounce burst, new keyboards and to notice having been suspended
+        let timeout = [
+            Some(RESUME_CHECK_INTERVAL),
+            Some(DEVICE_SCAN_INTERVAL.saturating_sub(last_scan.elapsed())),
+            watchdog_interval.map(|interval| interval.saturating_sub(last_watchdog.elapsed())),
+            hotkey_state.time_left(Instant::now()),
+            debounce.time_left(Instant::now()),
+        ].into_iter().flatten().min().unwrap_or(RESUME_CHECK_INTERVAL);
+        poll_with_timeout(input.as_raw_fd(), timeout, Some(requests.as_raw_fd()));
+        input.dispatch()?;
+
+        for event in &mut input {
+            let event = match event {
+                Event::Keyboard(KeyboardEvent::Key(event)) => event,
+                Event::Device(DeviceEvent::Removed(event)) if event.device().has_capability(DeviceCapability::Keyboard) => {
+                    // the keys held on it have been released already
+                    info!("Keyboard {} removed", event.device().name());
+                    continue;
+                }
+                _ => continue,
+            };
+            let Ok(keycode) = Keycode::try_from(event.key() + EVDEV_OFFSET) else {
+                continue; // beyond what X keycodes can express, so cannot be configured anyway
+            };
+            let pressed = event.key_state() == KeyState::Pressed;
+            // like for core events, the state does not yet include the own modifier of a pressed modifier key
+            let state = raw_key_state(&held_keys, &modifier_mapping);
+            if pressed {
+                held_keys.insert(keycode);
+            } else {
+                held_keys.remove(&keycode);
+            }
+            // modifiers count from any keyboard, the hotkey itself only from the --input-device
+            let action = input_device.is_none_or(|input_device| is_input_device(&event.device(), input_device))
+                .then(|| hotkey_map.get(&(state, keycode)))
+                .flatten();
+            debounce.handle(hotkey_state, pressed, Trigger::Key(keycode), action, event.time(), Instant::now());
+        }
+    }
+}
+
+/// Lists the keyboards, to find a value for --input-device
+pub fn list_input_devices() -> Result<(), PttError> {
+    let mut input = Libinput::new_from_path(Interface);
+    let keyboards = add_new_keyboards(&mut input, &mut HashSet::new())?;
+    println!("Keyboards, use the device or a part of the name for --input-device:");
+    println!("Device    Name");
+    for keyboard in keyboards {
+        println!("{:8}  {}", keyboard.sysname(), keyboard.name());
+    }
+    Ok(())
+}
+
+/// Adds the keyboards among the devices not seen before, returning them
+fn add_new_keyboards(input: &mut Libinput, known_paths: &mut HashSet<PathBuf>) -> Result<Vec<Device>, PttError> {
+    let mut paths = fs::read_dir(INPUT_DIR)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("event")))
+        .collect::<Vec<_>>();
+    paths.sort();
+    // a device unplugged meanwhile may come back under the same path
+    known_paths.retain(|path| paths.contains(path));
+
+    let mut keyboards = Vec::new();
+    for path in paths {
+        if !known_paths.insert(path.clone()) {
+            continue;
+        }
+        match input.path_add_device(&path.to_string_lossy()) {
+            Some(device) if device.has_capability(DeviceCapability::Keyboard) => {
+                info!("Watching keyboard {} ({})", device.name(), path.display());
+                keyboards.push(device);
+            }
+            Some(device) => input.path_remove_device(device),
+            None => debug!("Cannot open {}", path.display()),
+        }
+    }
+    Ok(keyboards)
+}
+
+/// Whether the keyboard is the given device, like event5, or its name contains the given text ignoring case
+fn is_input_device(device: &Device, name: &str) -> bool {
+    device.sysname() == name || device.name().to_lowercase().contains(&name.to_lowercase())
+}
@@ -0,0 +1,88 @@
+//! The `--status-socket`, a Unix socket sending the capture state to status bar scripts as a byte, 0 for muted and 1 for unmuted, on connecting and on every change
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use tracing::{debug, warn};
+
+use crate::control::{self, SocketFile};
+use crate::error::PttError;
+
+#[derive(Debug)]
+enum Message {
+    Connected(UnixStream),
+    StateChanged(bool),
+    /// the listening thread also holds a sender, so the channel does not end by itself
+    Stop,
+}
+
+static SENDER: Mutex<Option<Sender<Message>>> = Mutex::new(None);
+
+/// Sends the state to the connected clients from a thread of its own, so a slow client does not delay the hotkeys
+struct StatusServer {
+    state: bool,
+    subscribers: Vec<UnixStream>,
+}
+
+impl StatusServer {
+    fn connected(&mut self, mut subscriber: UnixStream) {
+        debug!("Status socket client connected");
+        if subscriber.write_all(&[self.state as u8]).is_ok() {
+            self.subscribers.push(subscriber);
+        }
+    }
+
+    fn state_changed(&mut self, state: bool) {
+        self.state = state;
+        // the clients that have disconnected fail to be written to
+        self.subscribers.retain_mut(|subscriber| subscriber.write_all(&[state as u8]).is_ok());
+    }
+}
+
+/// Listens on the socket, sending the states passed to `capture_state_changed` to every client
+pub fn start(path: &Path, state: bool) -> Result<SocketFile, PttError> {
+    let (listener, socket_file) = control::bind(path)?;
+    let (sender, receiver) = mpsc::channel();
+    {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for client in listener.incoming() {
+                match client {
+                    Ok(client) => {
+                        let _ = sender.send(Message::Connected(client));
+                    }
+                    Err(e) => warn!("Failed to accept status socket client: {}", e),
+                }
+            }
+        });
+    }
+    thread::spawn(move || {
+        let mut server = StatusServer { state, subscribers: Vec::new() };
+        while let Ok(message) = receiver.recv() {
+            match message {
+                Message::Connected(subscriber) => server.connected(subscriber),
+                Message::StateChanged(state) => server.state_changed(state),
+                Message::Stop => break,
+            }
+        }
+    });
+    *SENDER.lock().unwrap() = Some(sender);
+    Ok(socket_file)
+}
+
+pub fn capture_state_changed(state: bool) {
+    if let Some(sender) = SENDER.lock().unwrap().as_ref() {
+        let _ = sender.send(Message::StateChanged(state));
+    }
+}
+
+/// Disconnects the clients, which tells them that push-to-talk has stopped
+pub fn stop() {
+    if let Some(sender) = SENDER.lock().unwrap().take() {
+        let _ = sender.send(Message::Stop);
+    }
+}
@@ -0,0 +1,117 @@
+//! `--measure-latency`, timing each change from the hotkey event until the mixer has confirmed it, to help tune --unmute-delay
+//!
+//! The event loop notes the hotkey events and the state changes, the enforce threads when they have set the control
+//! and when its mixer reported the change back. With several devices the first one to get there counts.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info};
+
+static MEASUREMENTS: Mutex<Option<Measurements>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Measurements {
+    /// the latest hotkey event, as X server time in milliseconds and by the local clock
+    key_event: Option<(u32, Instant)>,
+    transition: Option<Transition>,
+    /// from the hotkey event until the mixer confirmed the change, including any unmute or remute delay
+    totals: Vec<Duration>,
+    /// from the change of the expected state until the mixer confirmed it
+    mixer: Vec<Duration>,
+}
+
+/// A change of the expected state that the mixer has not confirmed yet
+struct Transition {
+    state: bool,
+    key_event: Option<(u32, Instant)>,
+    requested: Instant,
+    set: Option<Instant>,
+}
+
+pub fn start() {
+    *MEASUREMENTS.lock().unwrap() = Some(Measurements::default());
+}
+
+/// Called by the event loop for each event of a key or button bound to a hotkey
+pub fn key_event(time: u32) {
+    if let Some(measurements) = MEASUREMENTS.lock().unwrap().as_mut() {
+        measurements.key_event = Some((time, Instant::now()));
+    }
+}
+
+/// Called by the event loop before waking up the enforce threads for a new state, so they always find the transition
+pub fn state_requested(state: bool) {
+    if let Some(measurements) = MEASUREMENTS.lock().unwrap().as_mut() {
+        measurements.transition = Some(Transition { state, key_event: measurements.key_event, requested: Instant::now(), set: None });
+    }
+}
+
+/// Called by an enforce thread once it has set the control
+pub fn switch_set(state: bool) {
+    if let Some(Transition { set: set @ None, .. }) = MEASUREMENTS.lock().unwrap().as_mut()
+        .and_then(|measurements| measurements.transition.as_mut())
+        .filter(|transition| transition.state == state) {
+        *set = Some(Instant::now());
+    }
+}
+
+/// Called by an enforce thread whenever the mixer reports the expected state
+pub fn switch_confirmed(state: bool) {
+    let mut measurements = MEASUREMENTS.lock().unwrap();
+    let Some(measurements) = measurements.as_mut() else {
+        return;
+    };
+    let Some(transition) = measurements.transition.take_if(|transition| transition.state == state && transition.set.is_some()) else {
+        return;
+    };
+    let confirmed = Instant::now();
+    let set = transition.set.unwrap_or(confirmed);
+    let mixer = confirmed.duration_since(transition.requested);
+    measurements.mixer.push(mixer);
+    let change = if state { "Unmuting" } else { "Muting" };
+    match transition.key_event {
+        Some((time, key_event)) => {
+            let total = confirmed.duration_since(key_event);
+            measurements.totals.push(total);
+            debug!("Hotkey event at X server time {} ms", time);
+            info!("{} took {:.1} ms from the hotkey event: {:.1} ms until requested, {:.1} ms until the switch was set, {:.1} ms until the mixer confirmed it",
+                change, millis(total), millis(transition.requested.duration_since(key_event)), millis(set.duration_since(key_event)), millis(total));
+        }
+        None => info!("{} took {:.1} ms from the request until the mixer confirmed it", change, millis(mixer)),
+    }
+}
+
+/// Logs the minimum, median and maximum of all changes so far, when exiting
+pub fn summary() {
+    let Some(measurements) = MEASUREMENTS.lock().unwrap().take() else {
+        return;
+    };
+    for (name, durations) in [("hotkey event until confirmed", measurements.totals), ("request until confirmed", measurements.mixer)] {
+        match min_median_max(durations) {
+            Some((min, median, max)) => info!("Latency from {}: min {:.1} ms, median {:.1} ms, max {:.1} ms", name, millis(min), millis(median), millis(max)),
+            None => info!("Latency from {}: nothing measured", name),
+        }
+    }
+}
+
+fn min_median_max(mut durations: Vec<Duration>) -> Option<(Duration, Duration, Duration)> {
+    durations.sort();
+    Some((*durations.first()?, durations[durations.len() / 2], *durations.last()?))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_median_max_of_unsorted_durations() {
+        let durations = [7, 1, 4, 9, 3].map(Duration::from_millis).to_vec();
+        assert_eq!(min_median_max(durations), Some((Duration::from_millis(1), Duration::from_millis(4), Duration::from_millis(9))));
+        assert_eq!(min_median_max(Vec::new()), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Keyboard LEDs showing the capture state, for `--led-on`, `--led-off` and `--led`
+
+use std::sync::{Arc, Mutex};
+
+use tracing::warn;
+use xcb::x::{self, Atom, ChangeKeyboardControl, InternAtom, Kb, LedMode};
+use xcb::{xkb, Connection};
+
+use crate::LockLed;
+
+#[derive(Debug, Clone, Copy)]
+struct Leds {
+    /// lit while unmuted, as index from 0
+    on: Option<u8>,
+    /// lit while muted, as index from 0
+    off: Option<u8>,
+    /// lit while unmuted and turned off when exiting
+    lock: Option<LockLed>,
+}
+
+static LEDS: Mutex<Option<Leds>> = Mutex::new(None);
+
+/// Replaced when reconnecting to the X server, with the atom naming the lock LED
+static X_CONN: Mutex<Option<(Arc<Connection>, Option<Atom>)>> = Mutex::new(None);
+
+pub fn start(on: Option<u8>, off: Option<u8>, lock: Option<LockLed>) {
+    *LEDS.lock().unwrap() = Some(Leds { on, off, lock });
+}
+
+/// Uses the new connection from now on, and shows the current state with it
+pub fn set_connection(x_conn: &Arc<Connection>, state: bool) {
+    let lock_atom = LEDS.lock().unwrap().and_then(|leds| leds.lock).and_then(|lock| match named_indicator(x_conn, lock) {
+        Ok(atom) => Some(atom),
+        Err(e) => {
+            warn!("Failed to use XKB for the {:?} lock LED: {}", lock, e);
+            None
+        }
+    });
+    *X_CONN.lock().unwrap() = Some((x_conn.clone(), lock_atom));
+    capture_state_changed(state);
+}
+
+/// Called when the connection to the X server is lost, so nothing is sent to it meanwhile
+pub fn forget_connection() {
+    *X_CONN.lock().unwrap() = None;
+}
+
+pub fn capture_state_changed(state: bool) {
+    set_leds(state, state);
+}
+
+/// Shows the final state, but leaves the lock LED off so it means the lock again, and lets go of the connection
+pub fn exit(state: bool) {
+    set_leds(state, false);
+    LEDS.lock().unwrap().take();
+    forget_connection();
+}
+
+fn set_leds(state: bool, lock_lit: bool) {
+    let Some(leds) = *LEDS.lock().unwrap() else {
+        return;
+    };
+    let Some((x_conn, lock_atom)) = X_CONN.lock().unwrap().clone() else {
+        return;
+    };
+    for (led, lit) in [(leds.on, state), (leds.off, !state)] {
+        if let Some(led) = led {
+            set_led(&x_conn, led, lit);
+        }
+    }
+    if let Some(atom) = lock_atom {
+        set_named_indicator(&x_conn, atom, lock_lit);
+    }
+}
+
+fn set_led(x_conn: &Connection, led: u8, lit: bool) {
+    // the core protocol numbers the LEDs from 1
+    let value_list = [Kb::Led(led as u32 + 1), Kb::LedMode(if lit { LedMode::On } else { LedMode::Off })];
+    if let Err(e) = x_conn.send_and_check_request(&ChangeKeyboardControl { value_list: &value_list }) {
+        warn!("Failed to turn keyboard LED {} {}: {}", led, if lit { "on" } else { "off" }, e);
+    }
+}
+
+/// Enables XKB on the connection and looks up the name of the indicator
+fn named_indicator(x_conn: &Connection, lock: LockLed) -> xcb::Result<Atom> {
+    x_conn.wait_for_reply(x_conn.send_request(&xkb::UseExtension { wanted_major: 1, wanted_minor: 0 }))?;
+    let name = match lock {
+        LockLed::Scroll => "Scroll Lock",
+        LockLed::Num => "Num Lock",
+        LockLed::Caps => "Caps Lock",
+    };
+    Ok(x_conn.wait_for_reply(x_conn.send_request(&InternAtom { only_if_exists: false, name: name.as_bytes() }))?.atom())
+}
+
+fn set_named_indicator(x_conn: &Connection, atom: Atom, lit: bool) {
+    let request = xkb::SetNamedIndicator {
+        device_spec: xkb::Id::UseCoreKbd as xkb::DeviceSpec,
+        led_class: xkb::LedClass::DfltXiClass,
+        led_id: xkb::Id::DfltXiId as xkb::IdSpec,
+        indicator: atom,
+        set_state: true,
+        on: lit,
+        set_map: false,
+        create_map: false,
+        map_flags: xkb::ImFlag::empty(),
+        map_which_groups: xkb::ImGroupsWhich::empty(),
+        map_groups: xkb::SetOfGroups::empty(),
+        map_which_mods: xkb::ImModsWhich::empty(),
+        map_real_mods: x::ModMask::empty(),
+        map_vmods: xkb::VMod::empty(),
+        map_ctrls: xkb::BoolCtrl::empty(),
+    };
+    if let Err(e) = x_conn.send_and_check_request(&request) {
+        warn!("Failed to turn the lock LED {}: {}", if lit { "on" } else { "off" }, e);
+    }
+}
@@ -0,0 +1,1715 @@
+use std::thread;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::mpsc::Receiver;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use alsa::Ctl;
+use alsa::mixer::{Selem, SelemChannelId};
+use capture_state::ExpectedCaptureState;
+use control::{Request, RequestSender, Requests, SocketFile};
+use debounce::Debounce;
+use error::PttError;
+use hotkey_state::{HotkeyState, KeyAction, Trigger};
+use mixer::{open_mixer, AlsaBackend, Capture, ControlId};
+#[cfg(feature = "pulse")]
+use mixer::pulse::PulseBackend;
+use pid_file::PidFile;
+use profile::{Profile, ProfileManager};
+use state_file::StateFileFormat;
+use state_reporter::StateReporter;
+use tracing::{debug, error, info, warn};
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+use signal_hook::iterator::{Handle, Signals};
+use xcb::{x, xinput, Connection, Xid, XidNew};
+use xcb::x::{Atom, Event, GetKeyboardMapping, GetModifierMapping, GrabKey, GrabMode, KeyButMask, Keycode, Keysym, ModMask, UngrabKey, Window};
+
+pub mod audio;
+pub mod capture_state;
+pub mod control;
+mod debounce;
+#[cfg(feature = "dbus")]
+mod dbus;
+pub mod error;
+mod feedback;
+pub mod hotkey_state;
+mod input;
+mod ipc;
+mod latency;
+pub mod mixer;
+mod pending_change;
+mod pid_file;
+pub mod profile;
+pub mod state_file;
+pub mod state_reporter;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+#[cfg(feature = "led")]
+mod led;
+#[cfg(feature = "notifications")]
+mod notifications;
+
+pub use mixer::{enforce_mixer_capture_state, get_alsa_mixer_capture_elem, get_unanimous_capture_state, set_capture_state};
+
+/// What `run` needs to know, like the commandline options of the same names with their shorthands resolved,
+/// so that push-to-talk can be embedded without going through the commandline
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub device: Vec<String>,
+    pub audio_backend: AudioBackend,
+    /// the mixer controls of each device, `auto` to pick one like --control auto
+    pub controls: Vec<Vec<ControlId>>,
+    pub method: Method,
+    pub capture_volume: Option<u8>,
+    pub muted_volume: u8,
+    pub restore_volume: bool,
+    pub playback_control: Option<String>,
+    pub unmute_delay: u64,
+    pub remute_delay: u64,
+    pub debounce: u64,
+    pub push_modifiers: ModMask,
+    pub push_keycode: Vec<Keycode>,
+    pub push_keysym: Option<String>,
+    pub push_hotkey: Vec<Hotkey>,
+    pub unmute_key: Vec<Hotkey>,
+    pub mute_key: Vec<Hotkey>,
+    pub panic_key: Option<Hotkey>,
+    pub push_button: Option<u32>,
+    pub toggle_modifiers: ModMask,
+    pub toggle_keycode: Vec<Keycode>,
+    pub toggle_keysym: Option<String>,
+    /// also false for --ignore-lock-modifiers
+    pub strict_modifiers: bool,
+    pub mode: Mode,
+    pub tap_toggle_threshold: u32,
+    pub double_tap_lock: u32,
+    pub min_hold: u32,
+    pub max_unmute_ms: Option<u64>,
+    pub auto_mute_idle: u64,
+    pub invert: bool,
+    pub beep: bool,
+    pub sound_on: Option<PathBuf>,
+    pub sound_off: Option<PathBuf>,
+    pub open_mic_reminder: Option<u64>,
+    pub reminder_sound: Option<PathBuf>,
+    pub beep_device: Option<String>,
+    pub no_repeat_filter: bool,
+    pub auto_mute_on_focus_loss: bool,
+    pub no_stuck_key_check: bool,
+    /// also false for --no-reconnect
+    pub reconnect: bool,
+    pub x11_reconnect_attempts: Option<u32>,
+    pub resume_state: ResumeState,
+    pub alsa_retry_limit: Option<u32>,
+    #[cfg(feature = "notifications")]
+    pub notify: bool,
+    #[cfg(feature = "led")]
+    pub led_on: Option<u8>,
+    #[cfg(feature = "led")]
+    pub led_off: Option<u8>,
+    #[cfg(feature = "led")]
+    pub led: Option<LockLed>,
+    pub exit_state: ExitState,
+    pub dry_run: bool,
+    pub no_sd_notify: bool,
+    pub pid_file: Option<PathBuf>,
+    pub input_backend: InputBackend,
+    pub display: Option<String>,
+    pub input_device: Option<String>,
+    pub passive: bool,
+    pub grab_retries: u32,
+    pub grab_retry_interval: u64,
+    pub grab_fallback: bool,
+    pub output: StateReporter,
+    pub control_socket: Option<PathBuf>,
+    #[cfg(feature = "dbus")]
+    pub dbus: bool,
+    pub status_socket: Option<PathBuf>,
+    pub signal_unmute_delay: u64,
+    pub state_file: Option<PathBuf>,
+    pub state_file_format: StateFileFormat,
+    pub measure_latency: bool,
+    /// from the [profiles] of the config file
+    pub profiles: Vec<Profile>,
+}
+
+/// The defaults of the commandline options
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            device: vec![String::from("default")],
+            audio_backend: AudioBackend::Alsa,
+            controls: vec![Vec::new()],
+            method: Method::Switch,
+            capture_volume: None,
+            muted_volume: 0,
+            restore_volume: false,
+            playback_control: None,
+            unmute_delay: 150,
+            remute_delay: 0,
+            debounce: 0,
+            push_modifiers: ModMask::N3,
+            push_keycode: vec![62],
+            push_keysym: None,
+            push_hotkey: Vec::new(),
+            unmute_key: Vec::new(),
+            mute_key: Vec::new(),
+            panic_key: None,
+            push_button: None,
+            toggle_modifiers: ModMask::N3 | ModMask::CONTROL,
+            toggle_keycode: vec![62],
+            toggle_keysym: None,
+            strict_modifiers: true,
+            mode: Mode::Push,
+            tap_toggle_threshold: 0,
+            double_tap_lock: 0,
+            min_hold: 0,
+            max_unmute_ms: None,
+            auto_mute_idle: 0,
+            invert: false,
+            beep: false,
+            sound_on: None,
+            sound_off: None,
+            open_mic_reminder: None,
+            reminder_sound: None,
+            beep_device: None,
+            no_repeat_filter: false,
+            auto_mute_on_focus_loss: false,
+            no_stuck_key_check: false,
+            reconnect: true,
+            x11_reconnect_attempts: None,
+            resume_state: ResumeState::Muted,
+            alsa_retry_limit: None,
+            #[cfg(feature = "notifications")]
+            notify: false,
+            #[cfg(feature = "led")]
+            led_on: None,
+            #[cfg(feature = "led")]
+            led_off: None,
+            #[cfg(feature = "led")]
+            led: None,
+            exit_state: ExitState::Muted,
+            dry_run: false,
+            no_sd_notify: false,
+            pid_file: None,
+            input_backend: InputBackend::X11,
+            display: None,
+            input_device: None,
+            passive: false,
+            grab_retries: 0,
+            grab_retry_interval: 1000,
+            grab_fallback: false,
+            output: StateReporter::Plain,
+            control_socket: None,
+            #[cfg(feature = "dbus")]
+            dbus: false,
+            status_socket: None,
+            signal_unmute_delay: 0,
+            state_file: None,
+            state_file_format: StateFileFormat::Text,
+            measure_latency: false,
+            profiles: Vec::new(),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// unmuted while the push hotkey is held down
+    Push,
+    /// each press of the push hotkey flips between muted and unmuted
+    Toggle,
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackend {
+    /// set the capture switch or volume of an alsa mixer control
+    Alsa,
+    /// mute the source with pactl, "default" being the default source
+    Pulse,
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputBackend {
+    /// grab the hotkeys from the X server
+    X11,
+    /// read the keyboards from /dev/input with libinput, needs the wayland feature and membership in the input group
+    Evdev,
+}
+
+#[cfg(feature = "led")]
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LockLed {
+    Scroll,
+    Num,
+    Caps,
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExitState {
+    Muted,
+    Unmuted,
+    /// as it was when starting, or muted if that could not be determined
+    Restore,
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResumeState {
+    /// muted, since releasing the push hotkey may have been missed while suspended
+    Muted,
+    /// unmuted, unless disarmed by the panic key
+    Unmuted,
+    /// as it was before suspending
+    Keep,
+}
+
+/// A hotkey given as a whole, with its own modifiers
+#[derive(Clone, Debug)]
+pub struct Hotkey {
+    pub modifiers: ModMask,
+    pub key: HotkeyKey,
+}
+
+#[derive(Clone, Debug)]
+pub enum HotkeyKey {
+    Keycode(Keycode),
+    /// resolved to keycodes once connected to X
+    Keysym(String),
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    /// turn the capture switch off and on
+    Switch,
+    /// set the capture volume to the minimum, and back to the previous volume of each channel
+    Volume,
+}
+
+/// Logs the error, with a hint at what to do about it where there is one
+pub fn log_error(e: &PttError) {
+    match e {
+        PttError::AlsaOpen { .. } => error!("{}, see --list-devices", e),
+        PttError::MixerControlNotFound { .. } | PttError::NoCaptureControl { .. } | PttError::NoCaptureSwitch | PttError::NoPlaybackSwitch(_) => error!("{}, see --list-controls", e),
+        PttError::AmbiguousControl(_) => error!("{}, choose one with --control", e),
+        PttError::X11Connect { .. } => error!("{}, check DISPLAY and XAUTHORITY or give --display and --xauthority", e),
+        PttError::InputDeviceNotFound(_) => error!("{}, see --list-input-devices", e),
+        PttError::HotkeyTaken { .. } => error!("{}, see --grab-retries, --grab-fallback and --passive", e),
+        #[cfg(feature = "wayland")]
+        PttError::NoKeyboards => error!("{}, reading the keyboards needs being in the input group", e),
+        _ => error!("{}", e),
+    }
+}
+/// Mutes and unmutes with the hotkeys until SIGINT, SIGTERM or a fatal error, then leaves the capture state as --exit-state says
+/// and returns the error if any; may be called again afterwards
+pub fn run(mut config: Config) -> Result<(), PttError> {
+    state_reporter::start(config.output);
+    resolve_controls(&mut config)?;
+    if config.dry_run {
+        warn!("Dry run, the hotkeys are handled as usual but the mixers are left as they are");
+    }
+
+    let pid_file = config.pid_file.as_deref().map(PidFile::create).transpose().map_err(PttError::PidFile)?;
+
+    // the only mixer of each device, handed over to its enforce thread; opened here to fail early on a wrong device or control
+    let mut backends = Vec::new();
+    let mut initially_unmuted = true;
+    for (index, (device, controls)) in config.device.iter().zip(&config.controls).enumerate() {
+        let backend = open_backend(&config, index, device, controls)?;
+        // if the channels disagree, muted is the safe choice to restore
+        initially_unmuted &= backend.get_capture().ok().flatten().unwrap_or(false);
+        backends.push((backend, device.clone()));
+    }
+
+    // all devices share the final state, so only unmuted if all of them were
+    let final_capture_state = match config.exit_state {
+        ExitState::Muted => false,
+        ExitState::Unmuted => true,
+        ExitState::Restore => initially_unmuted,
+    };
+
+    let read_wav = |path: &PathBuf| feedback::Wav::read(path).map_err(|source| PttError::SoundFile { path: path.clone(), source });
+    let unmute_wav = config.sound_on.as_ref().map(read_wav).transpose()?;
+    let mute_wav = config.sound_off.as_ref().map(read_wav).transpose()?;
+    let reminder_wav = config.reminder_sound.as_ref().map(read_wav).transpose()?;
+    if config.beep || unmute_wav.is_some() || mute_wav.is_some() || config.open_mic_reminder.is_some() {
+        // a source name is no alsa device to play on
+        let default_device = match config.audio_backend {
+            AudioBackend::Alsa => config.device[0].clone(),
+            AudioBackend::Pulse => String::from("default"),
+        };
+        feedback::start(config.beep_device.clone().unwrap_or(default_device), config.beep, unmute_wav, mute_wav, reminder_wav);
+    }
+    if config.measure_latency {
+        latency::start();
+    }
+    if config.auto_mute_on_focus_loss && config.input_backend != InputBackend::X11 {
+        warn!("--auto-mute-on-focus-loss only works with --input-backend x11");
+    }
+    if !config.profiles.is_empty() && (config.input_backend != InputBackend::X11 || config.audio_backend != AudioBackend::Alsa) {
+        warn!("The profiles of the config file only work with --input-backend x11 and --audio-backend alsa");
+    }
+    #[cfg(feature = "notifications")]
+    if config.notify {
+        notifications::start();
+    }
+    #[cfg(feature = "led")]
+    if config.led_on.is_some() || config.led_off.is_some() || config.led.is_some() {
+        if config.input_backend != InputBackend::X11 {
+            warn!("--led-on, --led-off and --led only work with --input-backend x11");
+        }
+        led::start(config.led_on, config.led_off, config.led);
+    }
+
+    // one logical state for all devices, but each enforce thread needs to be woken up separately
+    let expected_capture_state = Arc::new(ExpectedCaptureState::new(config.invert, backends.len())?);
+    // from the control socket, D-Bus and signals, carried out by the event loop
+    let (requests, request_sender) = control::channel()?;
+    let socket_file = config.control_socket.as_deref()
+        .map(|path| control::start(path, request_sender.clone(), expected_capture_state.clone()))
+        .transpose()?;
+    #[cfg(feature = "dbus")]
+    if config.dbus {
+        dbus::start(request_sender.clone(), expected_capture_state.clone())?;
+    }
+    if let Some(path) = &config.state_file {
+        state_file::start(path, config.state_file_format, expected_capture_state.get())?;
+    }
+    let status_socket_file = config.status_socket.as_deref()
+        .map(|path| ipc::start(path, expected_capture_state.get()))
+        .transpose()?;
+    let exit_signals = Signals::new([SIGINT, SIGTERM])?;
+    let request_signals = Signals::new([SIGUSR1, SIGUSR2])?;
+    let running = Arc::new(AtomicBool::new(true));
+
+    // the enforce threads report a fatal error instead of panicking, so that the capture state still gets set on exit
+    let (enforce_error_sender, enforce_error_receiver) = mpsc::channel();
+    let enforce_threads = backends.into_iter().enumerate()
+        .map(|(enforcer, (backend, device))| {
+            let expected_capture_state = expected_capture_state.clone();
+            let running = running.clone();
+            let enforce_error_sender = enforce_error_sender.clone();
+            let config = config.clone();
+            thread::spawn(move || {
+                if let Err(e) = enforce_mixer_capture_state(backend, &expected_capture_state, enforcer, &running, &device, &config) {
+                    let _ = enforce_error_sender.send(e);
+                }
+            })
+        })
+        .collect();
+    // only the enforce threads keep the channel open
+    drop(enforce_error_sender);
+
+    let shutdown = Arc::new(Shutdown {
+        final_capture_state,
+        expected_capture_state: expected_capture_state.clone(),
+        running,
+        enforce_threads: Mutex::new(enforce_threads),
+        x_grabs: Mutex::new(None),
+        pid_file: Mutex::new(pid_file),
+        socket_files: Mutex::new(socket_file.into_iter().chain(status_socket_file).collect()),
+        signals: vec![exit_signals.handle(), request_signals.handle()],
+        request_sender: request_sender.clone(),
+        stop: Mutex::new(None),
+        #[cfg(feature = "systemd")]
+        sd_notify: !config.no_sd_notify,
+    });
+
+    {
+        let shutdown = shutdown.clone();
+        thread::spawn(move || stop_on_signal(&shutdown, exit_signals));
+    }
+    {
+        let request_sender = request_sender.with_source("signal");
+        let signal_unmute_delay = config.signal_unmute_delay;
+        thread::spawn(move || request_on_signal(&request_sender, request_signals, signal_unmute_delay));
+    }
+    {
+        let shutdown = shutdown.clone();
+        thread::spawn(move || stop_on_enforce_error(&shutdown, enforce_error_receiver));
+    }
+
+    let mut hotkey_state = HotkeyState::new(expected_capture_state, config.unmute_delay, config.remute_delay, config.mode, config.invert, config.tap_toggle_threshold, config.double_tap_lock, config.min_hold, config.max_unmute_ms.map(Duration::from_millis), config.open_mic_reminder.map(Duration::from_secs), (config.auto_mute_idle > 0).then(|| Duration::from_secs(config.auto_mute_idle)));
+    let mut debounce = Debounce::new(Duration::from_millis(config.debounce));
+    let result = match config.input_backend {
+        InputBackend::X11 => listen_to_keyboard_events_and_update_mixer(&shutdown, &mut hotkey_state, &mut debounce, &requests, &config),
+        #[cfg(feature = "wayland")]
+        InputBackend::Evdev => input::evdev::listen(&shutdown, &mut hotkey_state, &mut debounce, &requests, &config),
+        #[cfg(not(feature = "wayland"))]
+        InputBackend::Evdev => Err(PttError::BackendNotBuilt("wayland")),
+    };
+    shutdown.finish();
+    result
+}
+
+/// Warns about options that do not apply, and picks the controls given as auto
+fn resolve_controls(config: &mut Config) -> Result<(), PttError> {
+    if config.audio_backend == AudioBackend::Pulse && (config.method != Method::Switch || config.capture_volume.is_some() || config.restore_volume) {
+        warn!("--method volume, --capture-volume and --restore-volume only work with --audio-backend alsa");
+    }
+    if config.audio_backend == AudioBackend::Pulse && config.playback_control.is_some() {
+        warn!("--playback-control only works with --audio-backend alsa");
+    }
+    for (device, controls) in config.device.iter().zip(config.controls.iter_mut()) {
+        for control in controls.iter_mut().filter(|control| control.name == "auto" && config.audio_backend == AudioBackend::Alsa) {
+            *control = auto_detect_capture_control(device, config.method)?;
+            info!("Using mixer control {} of alsa device {}", control, device);
+        }
+    }
+    Ok(())
+}
+
+/// Opens the mixer or source of the device, with the profiles taking turns with the controls of the first device
+fn open_backend(config: &Config, index: usize, device: &str, controls: &[ControlId]) -> Result<Box<dyn audio::AudioBackend + Send>, PttError> {
+    let playback_control = config.playback_control.as_deref().filter(|_| index == 0);
+    let capture = Capture::new(config.method, config.capture_volume, config.muted_volume, config.restore_volume);
+    Ok(match config.audio_backend {
+        AudioBackend::Alsa if index == 0 && !config.profiles.is_empty() => {
+            let default = AlsaBackend::open(device, controls, capture.clone())?
+                .with_playback_control(playback_control)?;
+            Box::new(ProfileManager::open(default, &config.profiles, &capture, config.dry_run)?)
+        }
+        AudioBackend::Alsa => Box::new(AlsaBackend::open(device, controls, capture)?
+            .with_playback_control(playback_control)?),
+        #[cfg(feature = "pulse")]
+        AudioBackend::Pulse => Box::new(PulseBackend::open(device)?),
+        #[cfg(not(feature = "pulse"))]
+        AudioBackend::Pulse => return Err(PttError::BackendNotBuilt("pulse")),
+    })
+}
+
+/// --check: goes through the setup up to opening the mixers and connecting to the X server, printing each step
+pub fn check(mut config: Config) -> Result<(), PttError> {
+    resolve_controls(&mut config)?;
+    println!("Arguments and config file: ok");
+    for (index, (device, controls)) in config.device.iter().zip(&config.controls).enumerate() {
+        let step = match config.audio_backend {
+            AudioBackend::Alsa => format!("Alsa device {} with mixer control {}", device, controls.iter().map(ControlId::to_string).collect::<Vec<_>>().join(", ")),
+            AudioBackend::Pulse => format!("Source {}", device),
+        };
+        let backend = report_step(&step, open_backend(&config, index, device, controls))?;
+        let state = match backend.get_capture()? {
+            Some(true) => "unmuted",
+            Some(false) => "muted",
+            None => "partly muted",
+        };
+        println!("  currently {}", state);
+    }
+    match config.input_backend {
+        InputBackend::X11 => {
+            let display = config.display.clone().or_else(|| env::var("DISPLAY").ok()).unwrap_or_default();
+            report_step(&format!("X display {}", display), open_x(config.display.as_deref()))?;
+        }
+        InputBackend::Evdev => println!("Keyboards of --input-backend evdev: not checked"),
+    }
+    println!("Everything ok, the hotkeys were not grabbed");
+    Ok(())
+}
+
+fn report_step<T>(step: &str, result: Result<T, PttError>) -> Result<T, PttError> {
+    println!("{}: {}", step, if result.is_ok() { "ok" } else { "failed" });
+    result
+}
+
+pub fn parse_modifiers(str: &str) -> Result<ModMask, &'static str> {
+    if !str.is_empty() {
+        str.split('+')
+            .map(parse_modifier)
+            .try_fold(ModMask::empty(), |prev, x| x.map(|cur| prev | cur))
+    } else {
+        Ok(ModMask::empty())
+    }
+}
+
+/// The opposite of `parse_modifiers`, e.g. `control+mod3`
+pub fn format_modifiers(modifiers: ModMask) -> String {
+    ["shift", "lock", "control", "mod1", "mod2", "mod3", "mod4", "mod5"].into_iter()
+        .filter(|name| parse_modifier(name).is_ok_and(|modifier| modifiers.contains(modifier)))
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Parses `[modifiers:]key`, where the modifiers are as for `parse_modifiers` or `0` for none and the key is a keycode or keysym name
+pub fn parse_hotkey_spec(str: &str) -> Result<Hotkey, String> {
+    let (modifiers, key) = match str.split_once(':') {
+        Some(("0", key)) => (ModMask::empty(), key),
+        Some((modifiers, key)) => (parse_modifiers(modifiers)?, key),
+        None => (ModMask::empty(), str),
+    };
+    let key = match key.parse::<Keycode>() {
+        Ok(keycode) if keycode != 0 => HotkeyKey::Keycode(keycode),
+        _ if key.starts_with(|c: char| c.is_ascii_digit()) => return Err(format!("expected keycode 1-255, got `{}`", key)),
+        _ if key.is_empty() => return Err("expected keycode or keysym after `:`".into()),
+        _ => HotkeyKey::Keysym(key.to_string()),
+    };
+    Ok(Hotkey { modifiers, key })
+}
+
+pub fn parse_modifier(str: &str) -> Result<ModMask, &'static str> {
+    match str {
+        "shift" => Ok(ModMask::SHIFT),
+        "lock" => Ok(ModMask::LOCK),
+        "control" => Ok(ModMask::CONTROL),
+        "mod1" => Ok(ModMask::N1),
+        "mod2" => Ok(ModMask::N2),
+        "mod3" => Ok(ModMask::N3),
+        "mod4" => Ok(ModMask::N4),
+        "mod5" => Ok(ModMask::N5),
+        _ => Err("expected modifier: `shift`, `lock`, `control`, `mod1`, `mod2`, `mod3`, `mod4`, or `mod5`"),
+    }
+}
+
+// -------------
+
+/// The current connection to the X server and the hotkeys grabbed on it, released when stopping
+struct XGrabs {
+    x_conn: Arc<Connection>,
+    roots: Vec<Window>,
+    grabs: Vec<(Keycode, ModMask)>,
+}
+
+/// Everything needed to leave the capture switch in a known state when stopping, from whichever thread
+pub(crate) struct Shutdown {
+    final_capture_state: bool,
+    expected_capture_state: Arc<ExpectedCaptureState>,
+    running: Arc<AtomicBool>,
+    enforce_threads: Mutex<Vec<JoinHandle<()>>>,
+    x_grabs: Mutex<Option<XGrabs>>,
+    pid_file: Mutex<Option<PidFile>>,
+    socket_files: Mutex<Vec<SocketFile>>,
+    /// closed when stopping, which ends the signal threads
+    signals: Vec<Handle>,
+    /// to wake the event loop up when stopping from another thread
+    request_sender: RequestSender,
+    /// what the event loop returns once stopped
+    stop: Mutex<Option<Result<(), PttError>>>,
+    #[cfg(feature = "systemd")]
+    sd_notify: bool,
+}
+
+impl Shutdown {
+    /// Called whenever the hotkeys are grabbed anew
+    fn set_x_grabs(&self, x_conn: &Arc<Connection>, roots: &[Window], grabs: &[(Keycode, ModMask)]) {
+        *self.x_grabs.lock().unwrap() = Some(XGrabs { x_conn: x_conn.clone(), roots: roots.to_vec(), grabs: grabs.to_vec() });
+    }
+
+    /// Called when the connection to the X server is lost, taking the grabs with it
+    fn forget_x_grabs(&self) {
+        *self.x_grabs.lock().unwrap() = None;
+    }
+
+    /// Makes the event loop return the result, keeping the first one if stopped from several threads
+    fn stop(&self, result: Result<(), PttError>) {
+        self.stop.lock().unwrap().get_or_insert(result);
+        self.request_sender.wake();
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stop.lock().unwrap().is_some()
+    }
+
+    /// The result for the event loop to return, once stopped
+    fn stopped(&self) -> Option<Result<(), PttError>> {
+        self.stop.lock().unwrap().take()
+    }
+
+    /// Stops enforcing, sets the final capture state, releases the hotkeys and cleans up what `run` started
+    fn finish(&self) {
+        info!("Setting capture state to {} and stopping", if self.final_capture_state { "unmuted" } else { "muted" });
+        #[cfg(feature = "systemd")]
+        if self.sd_notify {
+            systemd::stopping();
+        }
+
+        // the enforce threads set the final state before stopping, unless the mixer has failed
+        self.expected_capture_state.set(self.final_capture_state);
+        self.running.store(false, Ordering::Release);
+        self.expected_capture_state.wake();
+        for enforce_thread in self.enforce_threads.lock().unwrap().drain(..) {
+            let _ = enforce_thread.join();
+        }
+        latency::summary();
+        #[cfg(feature = "led")]
+        led::exit(self.final_capture_state);
+        // so that another hotkey tool started right away can grab them
+        if let Some(x_grabs) = self.x_grabs.lock().unwrap().take() {
+            ungrab_hotkeys(&x_grabs.grabs, &x_grabs.x_conn, &x_grabs.roots);
+        }
+        // the threads still holding the shutdown end only after this
+        drop(self.pid_file.lock().unwrap().take());
+        state_file::remove();
+        self.socket_files.lock().unwrap().clear();
+        ipc::stop();
+        feedback::stop();
+        #[cfg(feature = "notifications")]
+        notifications::stop();
+        #[cfg(feature = "dbus")]
+        dbus::stop();
+        for signals in &self.signals {
+            signals.close();
+        }
+    }
+}
+
+/// Stops at SIGINT or SIGTERM, unless the signals are closed first when stopping for another reason
+fn stop_on_signal(shutdown: &Shutdown, mut signals: Signals) {
+    if let Some(signal) = signals.forever().next() {
+        info!("Got signal {}", signal);
+        shutdown.stop(Ok(()));
+    }
+}
+
+/// SIGUSR1 mutes and SIGUSR2 unmutes, carried out by the event loop like the requests from the control socket
+fn request_on_signal(request_sender: &RequestSender, mut signals: Signals, unmute_delay_ms: u64) {
+    for signal in signals.forever() {
+        let request = if signal == SIGUSR1 { Request::Mute } else { Request::UnmuteAfter(unmute_delay_ms) };
+        if request_sender.send(request).is_none() {
+            warn!("No reply from the event loop to signal {}", signal);
+        }
+    }
+}
+
+fn stop_on_enforce_error(shutdown: &Shutdown, errors: Receiver<PttError>) {
+    // the sender is dropped without sending when the enforce thread stops normally
+    if let Ok(e) = errors.recv() {
+        shutdown.stop(Err(PttError::KeepCaptureState(Box::new(e))));
+    }
+}
+
+pub fn list_devices() -> Result<(), alsa::Error> {
+    let cards = alsa::card::Iter::new()
+        .map(|card| {
+            let card = card?;
+            let card_info = Ctl::from_card(&card, false)?.card_info()?;
+            Ok((card.get_index(), card_info.get_id()?.to_string(), card_info.get_longname()?.to_string()))
+        })
+        .collect::<Result<Vec<_>, alsa::Error>>()?;
+    if cards.is_empty() {
+        println!("No alsa sound cards found");
+        return Ok(());
+    }
+
+    let id_width = cards.iter().map(|(_, id, _)| id.len()).chain([2]).max().unwrap_or_default();
+    println!("Alsa sound cards, use e.g. --device hw:<id> (besides devices like default or pipewire from the alsa configuration):");
+    println!("Index  {:id_width$}  Name", "Id");
+    for (index, id, name) in cards {
+        println!("{:5}  {:id_width$}  {}", index, id, name);
+    }
+    Ok(())
+}
+
+/// Prints lines like `card 0: HDA Intel PCH (HDA Intel PCH at 0xf7f30000 irq 33)` followed by `  Capture [0] (has capture switch)` for each control
+pub fn list_cards_and_controls() -> Result<(), alsa::Error> {
+    for card in alsa::card::Iter::new() {
+        let card = card?;
+        println!("card {}: {} ({})", card.get_index(), card.get_name()?, card.get_longname()?);
+        let alsa_mixer = match open_mixer(&format!("hw:{}", card.get_index())) {
+            Ok(alsa_mixer) => alsa_mixer,
+            Err(e) => {
+                println!("  {}", e);
+                continue;
+            }
+        };
+        for selem in alsa_mixer.iter().filter_map(Selem::new) {
+            let id = selem.get_id();
+            let note = if selem.has_capture_switch() { " (has capture switch)" } else { "" };
+            println!("  {} [{}]{}", id.get_name()?, id.get_index(), note);
+        }
+    }
+    Ok(())
+}
+
+pub fn list_controls(device: &str) -> Result<(), PttError> {
+    let alsa_mixer = open_mixer(device)?;
+    let rows = alsa_mixer.iter()
+        .filter_map(Selem::new)
+        .map(|selem| {
+            let id = selem.get_id();
+            let channels = if !selem.has_capture_switch() && !selem.has_capture_volume() {
+                String::from("-")
+            } else if selem.is_capture_mono() {
+                String::from("mono")
+            } else {
+                SelemChannelId::all().iter()
+                    .filter(|channel| selem.has_capture_channel(**channel))
+                    .map(|channel| channel.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let yes_no = |value| if value { "yes" } else { "no" };
+            Ok((id.get_name()?.to_string(), id.get_index(), yes_no(selem.has_capture_switch()), yes_no(selem.has_capture_volume()), channels))
+        })
+        .collect::<Result<Vec<_>, alsa::Error>>()
+        .map_err(|source| PttError::ListControls { device: device.to_string(), source })?;
+
+    let name_width = rows.iter().map(|(name, ..)| name.len()).chain([7]).max().unwrap_or_default();
+    println!("Mixer controls of alsa device {}:", device);
+    println!("{:name_width$}  Index  Capture switch  Capture volume  Capture channels", "Control");
+    for (name, index, switch, volume, channels) in rows {
+        println!("{:name_width$}  {:5}  {:14}  {:14}  {}", name, index, switch, volume, channels);
+    }
+    Ok(())
+}
+
+/// Prints the keyboards --input-device can choose from, as seen by the given input backend
+pub fn list_input_devices(input_backend: InputBackend, display: Option<&str>) -> Result<(), PttError> {
+    let result = match input_backend {
+        InputBackend::X11 => open_x(display).and_then(|(x_conn, _)| list_xinput_devices(&x_conn)),
+        #[cfg(feature = "wayland")]
+        InputBackend::Evdev => input::evdev::list_input_devices(),
+        #[cfg(not(feature = "wayland"))]
+        InputBackend::Evdev => Err(PttError::BackendNotBuilt("wayland")),
+    };
+    result.map_err(|e| PttError::ListInputDevices(Box::new(e)))
+}
+
+/// The only control of the device that can be muted with the method, or if there are several, the only one that also has a capture volume;
+/// without any capture switch, the only control with a capture volume
+pub fn auto_detect_capture_control(device: &str, method: Method) -> Result<ControlId, PttError> {
+    let alsa_mixer = open_mixer(device)?;
+    let candidates = alsa_mixer.iter()
+        .filter_map(Selem::new)
+        .filter(|selem| match method {
+            Method::Switch => selem.has_capture_switch(),
+            Method::Volume => selem.has_capture_volume(),
+        })
+        .map(|selem| Ok((ControlId::new(selem.get_id().get_name()?, selem.get_id().get_index()), selem.has_capture_volume())))
+        .collect::<Result<Vec<_>, alsa::Error>>()?;
+    let with_volume = candidates.iter().filter(|(_, has_volume)| *has_volume).collect::<Vec<_>>();
+    match (candidates.as_slice(), with_volume.as_slice()) {
+        // muted with the capture volume then, like any control without a capture switch
+        ([], _) if method == Method::Switch => auto_detect_capture_control(device, Method::Volume),
+        ([], _) => Err(PttError::NoCaptureControl { device: device.to_string(), method: if method == Method::Switch { "switch" } else { "volume" } }),
+        ([(name, _)], _) | (_, [(name, _)]) => Ok(name.clone()),
+        _ => {
+            let names = candidates.iter().map(|(control, _)| format!("\"{}\"", control)).collect::<Vec<_>>().join(", ");
+            Err(PttError::AmbiguousControl(names))
+        }
+    }
+}
+
+// -------------
+
+/// How long to wait after a key release for the press of a repeated key event, sometimes it arrives some 3..6ms later
+const REPEAT_PRESS_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// How often to check that the held push keys are still down, for --no-stuck-key-check
+const STUCK_KEY_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long to wait for further notifications after the keyboard mapping changed, before grabbing the hotkeys again
+const MAPPING_NOTIFY_SETTLE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How long to wait before the first attempt to reconnect to the X server, doubled for each failed attempt
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often the reconnect backoff checks whether to stop
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often to check whether the system was suspended, since the hotkeys may need grabbing again afterwards
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) fn listen_to_keyboard_events_and_update_mixer(shutdown: &Shutdown, hotkey_state: &mut HotkeyState, debounce: &mut Debounce, requests: &Requests, config: &Config) -> Result<(), PttError> {
+    let repeat_filter = !config.no_repeat_filter;
+    let window_profiles = !config.profiles.is_empty();
+    let input_device = config.input_device.as_deref();
+    let display = config.display.as_deref();
+    // turned on by --grab-fallback if the hotkeys cannot be grabbed; --input-device also needs the raw key events
+    let passive = Cell::new(config.passive || input_device.is_some());
+    // the hotkeys are grabbed on the root window of every screen, and events from any of them drive the same state
+    let (x_conn, mut roots) = open_x(display)?;
+    // shared with the shutdown, to release the hotkeys when exiting
+    let mut x_conn = Arc::new(x_conn);
+
+    let push_modifier_combinations = modifier_combinations(config.push_modifiers, config.strict_modifiers);
+    let toggle_modifier_combinations = modifier_combinations(config.toggle_modifiers, config.strict_modifiers);
+
+    // resolves and grabs the keys anew, since keysyms and modifier keys depend on the keyboard mapping
+    // the grabbed keycode and modifier pairs, the actions by the modifier state and keycode of key events, and the modifier of each modifier key
+    type GrabbedKeys = (Vec<(Keycode, ModMask)>, HashMap<(KeyButMask, Keycode), KeyAction>, HashMap<Keycode, ModMask>);
+    let grab_keys = |x_conn: &Connection, roots: &[Window]| -> Result<GrabbedKeys, PttError> {
+        let mut keyboard_mapping = None;
+
+        let push_keycodes = get_keycodes_for_keysym(x_conn, &mut keyboard_mapping, &config.push_keycode, config.push_keysym.clone())?;
+        let toggle_keycodes = get_keycodes_for_keysym(x_conn, &mut keyboard_mapping, &config.toggle_keycode, config.toggle_keysym.clone())?;
+
+        // the --push-hotkey, --unmute-key, --mute-key and --panic-key bindings each have their own modifiers
+        let mut extra_hotkeys = Vec::new();
+        for (hotkeys, action) in [(config.push_hotkey.as_slice(), KeyAction::Push), (config.unmute_key.as_slice(), KeyAction::Unmute), (config.mute_key.as_slice(), KeyAction::Mute), (config.panic_key.as_slice(), KeyAction::Panic)] {
+            for hotkey in hotkeys {
+                let keycodes = match &hotkey.key {
+                    HotkeyKey::Keycode(keycode) => vec![*keycode],
+                    HotkeyKey::Keysym(name) => get_keycodes_for_keysym(x_conn, &mut keyboard_mapping, &[], Some(name.clone()))?,
+                };
+                extra_hotkeys.push((modifier_combinations(hotkey.modifiers, config.strict_modifiers), keycodes, action.clone()));
+            }
+        }
+
+        drop(keyboard_mapping);
+
+        // a modifier key has its own modifier set when released, and when pressed while another key of that modifier is held
+        let keycode_to_modifier = get_modifier_mapping(x_conn)?;
+        let own_modifier = |keycode: &Keycode| keycode_to_modifier.get(keycode).cloned().unwrap_or_else(ModMask::empty);
+
+        let push_grabs = key_grabs(&push_keycodes, &push_modifier_combinations, own_modifier);
+        let toggle_grabs = key_grabs(&toggle_keycodes, &toggle_modifier_combinations, own_modifier);
+        let extra_grabs = extra_hotkeys.iter()
+            .map(|(modifier_combinations, keycodes, action)| (key_grabs(keycodes, modifier_combinations, own_modifier), action.clone()))
+            .collect::<Vec<_>>();
+
+        let push_entries = hotkey_entries(&push_grabs, KeyAction::Push);
+        let toggle_entries = hotkey_entries(&toggle_grabs, KeyAction::Toggle);
+        let extra_entries = extra_grabs.iter()
+            .flat_map(|(grabs, action)| hotkey_entries(grabs, action.clone()));
+
+        // the same for press and release events, since the grabs cover the modifier key states of both
+        let hotkey_map = try_collect_map(push_entries.chain(extra_entries).chain(toggle_entries))?;
+
+        // in passive mode nothing is grabbed, the raw key events are looked up the same way
+        let all_grabs = if passive.get() {
+            Vec::new()
+        } else {
+            push_grabs.iter().chain(extra_grabs.iter().flat_map(|(grabs, _)| grabs)).chain(&toggle_grabs).copied().collect()
+        };
+        grab_hotkeys(&all_grabs, x_conn, roots)?;
+        Ok((all_grabs, hotkey_map, keycode_to_modifier))
+    };
+    // everything that has to be grabbed again on a new connection, and the ids of the --input-device keyboards, which may differ after reconnecting
+    type Grabbed = (GrabbedKeys, Option<HashSet<u16>>);
+    let grab_all = |x_conn: &Connection, roots: &[Window]| -> Result<Grabbed, PttError> {
+        let input_device_ids = input_device.map(|input_device| find_input_devices(x_conn, input_device)).transpose()?;
+        let grabbed_keys = grab_keys(x_conn, roots)?;
+        if passive.get() {
+            select_raw_key_events(x_conn, roots[0])?;
+        }
+        if let Some(button) = config.push_button {
+            grab_button(&push_modifier_combinations, button, x_conn, roots)?;
+        }
+        Ok((grabbed_keys, input_device_ids))
+    };
+    // another application may hold a hotkey only for a while, e.g. when both are started at login
+    let grab_all_with_retries = |x_conn: &Connection, roots: &[Window]| -> Result<Grabbed, PttError> {
+        let mut retries = 0;
+        let mut interval = Duration::from_millis(config.grab_retry_interval);
+        loop {
+            match grab_all(x_conn, roots) {
+                Err(e @ PttError::HotkeyTaken { .. }) => {
+                    if retries < config.grab_retries {
+                        retries += 1;
+                        warn!("{}, retrying in {:?} ({}/{})", e, interval, retries, config.grab_retries);
+                        thread::sleep(interval);
+                        interval *= 2;
+                    } else if config.grab_fallback && !passive.get() {
+                        warn!("{}, watching the hotkeys with raw key events instead", e);
+                        passive.set(true);
+                    } else {
+                        return Err(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+    };
+    let ((mut grabbed_keys, mut hotkey_map, mut modifier_mapping), mut input_device_ids) = grab_all_with_retries(&x_conn, &roots)?;
+    shutdown.set_x_grabs(&x_conn, &roots, &grabbed_keys);
+
+    // buttons are not modifiers, so presses and releases have the same modifier state
+    let button_map: HashMap<(u32, u32), KeyAction> = config.push_button.iter()
+        .flat_map(|button| push_modifier_combinations.iter().map(move |modifiers| ((modifiers.bits(), *button), KeyAction::Push)))
+        .collect();
+
+    // in passive mode, the keys held down, since raw key events carry no modifier state and also repeat
+    let mut held_raw_keys = HashSet::new();
+
+    // with detectable autorepeat, a held key repeats its press without releases in between, so the held keys tell the repeats
+    let mut detectable_autorepeat = repeat_filter && enable_detectable_autorepeat(&x_conn);
+    let mut held_keys = HashSet::new();
+
+    let mut last_stuck_key_check = Instant::now();
+
+    let focus_window = config.auto_mute_on_focus_loss.then(|| own_window(&x_conn)).transpose()?;
+    if let Some(window) = focus_window {
+        watch_focus(&x_conn, window)?;
+    }
+
+    // for the profiles, the atom whose changes on the root window tell that another window became active
+    let mut active_window_atom = window_profiles.then(|| watch_active_window(&x_conn, roots[0])).transpose()?;
+    let mut active_window_class = None;
+    if let Some(atom) = active_window_atom {
+        active_window_changed(&x_conn, roots[0], atom, &mut active_window_class, &shutdown.expected_capture_state);
+    }
+
+    #[cfg(feature = "led")]
+    led::set_connection(&x_conn, hotkey_state.target_state());
+
+    let watchdog_interval = notify_ready(hotkey_state, !config.no_sd_notify);
+    let mut last_watchdog = Instant::now();
+
+    let mut suspended = suspended_time();
+
+    let mut next_event_maybe: Option<xcb::Result<xcb::Event>> = None;
+    loop {
+        if let Some(result) = shutdown.stopped() {
+            return result;
+        }
+        ping_watchdog(watchdog_interval, &mut last_watchdog);
+
+        // input devices may have been reset while suspended, taking the grabs with them, and the mixers may be stale
+        let previously_suspended = std::mem::replace(&mut suspended, suspended_time());
+        if suspended.saturating_sub(previously_suspended) >= RESUME_CHECK_INTERVAL {
+            info!("Resumed after being suspended for {:?}, grabbing the hotkeys again", suspended - previously_suspended);
+            held_raw_keys.clear();
+            held_keys.clear();
+            debounce.discard();
+            hotkey_state.resumed(config.resume_state);
+            shutdown.expected_capture_state.request_reopen();
+            ungrab_hotkeys(&grabbed_keys, &x_conn, &roots);
+            match grab_all_with_retries(&x_conn, &roots) {
+                Ok(grabbed) => {
+                    ((grabbed_keys, hotkey_map, modifier_mapping), input_device_ids) = grabbed;
+                    shutdown.set_x_grabs(&x_conn, &roots, &grabbed_keys);
+                }
+                Err(e) => return Err(PttError::Regrab(Box::new(e))),
+            }
+        }
+
+        requests.handle_requests(hotkey_state);
+        debounce.apply_quiet(hotkey_state, Instant::now());
+        hotkey_state.apply_due_change(Instant::now());
+
+        // the release of a push key gets lost e.g. when switching to a text console while holding it
+        if !config.no_stuck_key_check && next_event_maybe.is_none() && last_stuck_key_check.elapsed() >= STUCK_KEY_CHECK_INTERVAL {
+            last_stuck_key_check = Instant::now();
+            let held_push_keys = hotkey_state.held_push_keys();
+            if !held_push_keys.is_empty() {
+                match x_conn.wait_for_reply(x_conn.send_request(&x::QueryKeymap {})) {
+                    Ok(keymap) => match x_conn.poll_for_queued_event() {
+                        Ok(None) => {
+                            for keycode in held_push_keys.into_iter().filter(|keycode| !key_down(keymap.keys(), *keycode)) {
+                                hotkey_state.stuck_key_released(keycode);
+                            }
+                        }
+                        // a release that arrived meanwhile is handled as usual instead
+                        queued => next_event_maybe = queued.map_err(xcb::Error::Protocol).transpose(),
+                    },
+                    Err(e) => debug!("Failed to check for stuck keys: {}", e),
+                }
+            }
+        }
+
+        let event = match next_event_maybe.take() {
+            Some(event) => event,
+            None => {
+                // wakes up in time for the watchdog, a delayed change, the end of a --debounce burst and to notice having been suspended
+                let timeout = [
+                    Some(RESUME_CHECK_INTERVAL),
+                    watchdog_interval.map(|interval| interval.saturating_sub(last_watchdog.elapsed())),
+                    hotkey_state.time_left(Instant::now()),
+                    debounce.time_left(Instant::now()),
+                ].into_iter().flatten().min().unwrap_or(RESUME_CHECK_INTERVAL);
+                match wait_for_event_with_timeout(&x_conn, timeout, Some(requests.as_raw_fd())) {
+                    Some(event) => event,
+                    None => continue,
+                }
+            }
+        };
+        let event = match event {
+            Err(e) if !config.reconnect => return Err(PttError::X11ConnectionLost(e)),
+            Err(e) => {
+                warn!("Lost the connection to the X server: {}", e);
+                shutdown.forget_x_grabs();
+                #[cfg(feature = "led")]
+                led::forget_connection();
+                debounce.discard();
+                hotkey_state.disconnected();
+                held_raw_keys.clear();
+                held_keys.clear();
+                next_event_maybe = None;
+
+                let mut backoff = RECONNECT_INITIAL_BACKOFF;
+                let mut attempt = 1;
+                let grabbed = loop {
+                    match config.x11_reconnect_attempts {
+                        Some(limit) => info!("Reconnecting to the X server in {:?} (attempt {}/{})", backoff, attempt, limit),
+                        None => info!("Reconnecting to the X server in {:?} (attempt {})", backoff, attempt),
+                    }
+                    sleep_with_watchdog(backoff, watchdog_interval, &mut last_watchdog, shutdown);
+                    if let Some(result) = shutdown.stopped() {
+                        return result;
+                    }
+                    match open_x(display).and_then(|(new_x_conn, new_roots)| grab_all_with_retries(&new_x_conn, &new_roots).map(|grabbed| (Arc::new(new_x_conn), new_roots, grabbed))) {
+                        Ok(grabbed) => break grabbed,
+                        Err(e) if config.x11_reconnect_attempts.is_some_and(|limit| attempt >= limit) => {
+                            return Err(PttError::X11ReconnectLimit { attempts: attempt, source: Box::new(e) });
+                        }
+                        Err(e) => warn!("Failed to reconnect to the X server: {}", e),
+                    }
+                    backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+                    attempt += 1;
+                };
+                // replacing the connection closes the old one
+                (x_conn, roots, ((grabbed_keys, hotkey_map, modifier_mapping), input_device_ids)) = grabbed;
+                shutdown.set_x_grabs(&x_conn, &roots, &grabbed_keys);
+                detectable_autorepeat = repeat_filter && enable_detectable_autorepeat(&x_conn);
+                if let Some(Err(e)) = focus_window.map(|window| watch_focus(&x_conn, window)) {
+                    warn!("{}, no longer muting on focus loss", e);
+                }
+                if window_profiles {
+                    active_window_atom = watch_active_window(&x_conn, roots[0])
+                        .inspect_err(|e| warn!("{}, no longer switching profiles", e))
+                        .ok();
+                    if let Some(atom) = active_window_atom {
+                        active_window_changed(&x_conn, roots[0], atom, &mut active_window_class, &shutdown.expected_capture_state);
+                    }
+                }
+                info!("Reconnected to the X server");
+                #[cfg(feature = "led")]
+                led::set_connection(&x_conn, hotkey_state.target_state());
+                hotkey_state.reconnected();
+                continue;
+            }
+            Ok(e) => e,
+        };
+        let (pressed, trigger, action, time) = match &event {
+            xcb::Event::X(Event::KeyPress(evt)) => {
+                if !held_keys.insert(evt.detail()) && detectable_autorepeat {
+                    debug!("Skipping autorepeated press of keycode {}", evt.detail());
+                    continue;
+                }
+                (true, Trigger::Key(evt.detail()), hotkey_map.get(&(evt.state(), evt.detail())), evt.time())
+            }
+            xcb::Event::X(Event::KeyRelease(evt)) => {
+                // skip repeated key events (e.g. Pause key), without detectable autorepeat it sends a release and a press with the same timestamp
+                if repeat_filter && !detectable_autorepeat {
+                    next_event_maybe = poll_for_event_with_timeout(&x_conn, REPEAT_PRESS_TIMEOUT);
+                    if let Some(Ok(xcb::Event::X(Event::KeyPress(press_evt)))) = &next_event_maybe {
+                        if press_evt.detail() == evt.detail() && press_evt.time() == evt.time() {
+                            debug!("Skipping autorepeated release and press of keycode {}", evt.detail());
+                            next_event_maybe = None; // skip both next press event and ..
+                            continue; // current release event
+                        }
+                    }
+                }
+                held_keys.remove(&evt.detail());
+                (false, Trigger::Key(evt.detail()), hotkey_map.get(&(evt.state(), evt.detail())), evt.time())
+            }
+            xcb::Event::Input(xinput::Event::RawKeyPress(evt)) => {
+                let keycode = evt.detail() as Keycode;
+                // like for core events, the state does not yet include the own modifier of a pressed modifier key
+                let state = raw_key_state(&held_raw_keys, &modifier_mapping);
+                if !held_raw_keys.insert(keycode) {
+                    continue; // repeated
+                }
+                // modifiers count from any keyboard, the hotkey itself only from the --input-device
+                let action = from_input_device(&input_device_ids, evt.source()).then(|| hotkey_map.get(&(state, keycode))).flatten();
+                (true, Trigger::Key(keycode), action, evt.time())
+            }
+            xcb::Event::Input(xinput::Event::RawKeyRelease(evt)) => {
+                let keycode = evt.detail() as Keycode;
+                let state = raw_key_state(&held_raw_keys, &modifier_mapping);
+                held_raw_keys.remove(&keycode);
+                let action = from_input_device(&input_device_ids, evt.source()).then(|| hotkey_map.get(&(state, keycode))).flatten();
+                (false, Trigger::Key(keycode), action, evt.time())
+            }
+            xcb::Event::Input(xinput::Event::ButtonPress(evt)) => (true, Trigger::Button(evt.detail()), button_map.get(&(evt.mods().effective & 0xff, evt.detail())), evt.time()),
+            xcb::Event::Input(xinput::Event::ButtonRelease(evt)) => (false, Trigger::Button(evt.detail()), button_map.get(&(evt.mods().effective & 0xff, evt.detail())), evt.time()),
+            xcb::Event::X(Event::MappingNotify(evt)) if evt.request() != x::Mapping::Pointer => {
+                // setxkbmap and layout switches send several in a row, so grab only once after the last one
+                let mut notifications = 1;
+                loop {
+                    next_event_maybe = poll_for_event_with_timeout(&x_conn, MAPPING_NOTIFY_SETTLE_TIMEOUT);
+                    match &next_event_maybe {
+                        Some(Ok(xcb::Event::X(Event::MappingNotify(evt)))) if evt.request() != x::Mapping::Pointer => notifications += 1,
+                        _ => break,
+                    }
+                }
+                debug!("Got {} keyboard mapping notifications", notifications);
+                info!("Keyboard mapping changed, grabbing the hotkeys again");
+                ungrab_hotkeys(&grabbed_keys, &x_conn, &roots);
+                match grab_keys(&x_conn, &roots) {
+                    Ok((grabs, map, modifiers)) => {
+                        grabbed_keys = grabs;
+                        hotkey_map = map;
+                        modifier_mapping = modifiers;
+                        shutdown.set_x_grabs(&x_conn, &roots, &grabbed_keys);
+                    }
+                    Err(e) => return Err(PttError::Regrab(Box::new(e))),
+                }
+                continue;
+            }
+            // the grab of a held hotkey also takes the focus for a while, and the focus may move within the window
+            xcb::Event::X(Event::FocusOut(evt)) if matches!(evt.mode(), x::NotifyMode::Normal | x::NotifyMode::WhileGrabbed) && evt.detail() != x::NotifyDetail::Inferior => {
+                hotkey_state.focus_lost();
+                continue;
+            }
+            xcb::Event::X(Event::PropertyNotify(evt)) if Some(evt.atom()) == active_window_atom => {
+                active_window_changed(&x_conn, roots[0], evt.atom(), &mut active_window_class, &shutdown.expected_capture_state);
+                continue;
+            }
+            xcb::Event::X(_) => continue,
+            e => return Err(PttError::UnsupportedEvent(format!("{:#?}", e))),
+        };
+        debounce.handle(hotkey_state, pressed, trigger, action, time, Instant::now());
+    }
+}
+
+/// Tells systemd that the setup is done, returning how often to ping its watchdog
+fn notify_ready(hotkey_state: &HotkeyState, sd_notify: bool) -> Option<Duration> {
+    // status bars need to know the state from the start
+    state_reporter::capture_state_changed(hotkey_state.target_state());
+
+    // a systemd service of Type=notify is now started
+    if !sd_notify {
+        return None;
+    }
+    #[cfg(feature = "systemd")]
+    return systemd::ready();
+    #[cfg(not(feature = "systemd"))]
+    None
+}
+
+/// Tells systemd that the program is still alive, if the watchdog is enabled and it is time for it
+fn ping_watchdog(watchdog_interval: Option<Duration>, last_watchdog: &mut Instant) {
+    if let Some(interval) = watchdog_interval {
+        if last_watchdog.elapsed() >= interval {
+            #[cfg(feature = "systemd")]
+            systemd::watchdog();
+            *last_watchdog = Instant::now();
+        }
+    }
+}
+
+/// The time spent suspended since booting, which the monotonic clock leaves out
+fn suspended_time() -> Duration {
+    let clock = |clock_id| {
+        let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(clock_id, &mut time) };
+        Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+    };
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+/// Sleeps without letting the watchdog run out meanwhile, waking up early when stopping
+fn sleep_with_watchdog(duration: Duration, watchdog_interval: Option<Duration>, last_watchdog: &mut Instant, shutdown: &Shutdown) {
+    let deadline = Instant::now() + duration;
+    loop {
+        ping_watchdog(watchdog_interval, last_watchdog);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || shutdown.is_stopping() {
+            return;
+        }
+        thread::sleep(match watchdog_interval {
+            Some(interval) => remaining.min(interval.saturating_sub(last_watchdog.elapsed())),
+            None => remaining,
+        }.min(STOP_CHECK_INTERVAL));
+    }
+}
+
+/// Like `wait_for_event`, but gives up after the timeout
+fn wait_for_event_with_timeout(x_conn: &Connection, timeout: Duration, wake_fd: Option<RawFd>) -> Option<xcb::Result<xcb::Event>> {
+    match x_conn.poll_for_event() {
+        Ok(Some(event)) => return Some(Ok(event)),
+        Ok(None) => (),
+        Err(e) => return Some(Err(e)),
+    }
+    poll_with_timeout(x_conn.as_raw_fd(), timeout, wake_fd);
+    x_conn.poll_for_event().transpose()
+}
+
+/// Waits until the fd is readable or the timeout passes, or earlier when the wake fd, e.g. of the control socket, becomes readable
+fn poll_with_timeout(fd: RawFd, timeout: Duration, wake_fd: Option<RawFd>) {
+    let mut fds = [fd].into_iter().chain(wake_fd)
+        .map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+        .collect::<Vec<_>>();
+    // an error or timeout just means there is nothing to read afterwards
+    unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout.as_millis().min(i32::MAX as u128) as i32) };
+}
+
+/// Returns the next event if one arrives within the timeout, without blocking on the connection
+fn poll_for_event_with_timeout(x_conn: &Connection, timeout: Duration) -> Option<xcb::Result<xcb::Event>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match x_conn.poll_for_event() {
+            Ok(Some(event)) => return Some(Ok(event)),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(1)),
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+fn try_collect_map<K: Debug + Eq + Hash, V: Debug, I: Iterator<Item = (K, V)>>(mut entries: I) -> Result<HashMap<K, V>, PttError> {
+    entries
+        .try_fold(HashMap::new(), |mut map, (k, v)|
+            if map.insert(k, v).is_none() {
+                Ok(map)
+            } else {
+                Err(PttError::ConflictingKeybindings)
+            })
+}
+
+/// The keycode and modifier pairs to grab, for modifier keys both with and without their own modifier
+fn key_grabs(keycodes: &[Keycode], modifier_combinations: &[ModMask], own_modifier: impl Fn(&Keycode) -> ModMask) -> Vec<(Keycode, ModMask)> {
+    let mut grabs = Vec::new();
+    for keycode in keycodes {
+        let own_modifier = own_modifier(keycode);
+        for modifiers in modifier_combinations {
+            for modifiers in [*modifiers, *modifiers | own_modifier] {
+                if !grabs.contains(&(*keycode, modifiers)) {
+                    grabs.push((*keycode, modifiers));
+                }
+            }
+        }
+    }
+    grabs
+}
+
+/// Map entries for looking up the action by the modifier state and keycode of key events
+fn hotkey_entries<'a, V: Clone + 'a>(grabs: &'a [(Keycode, ModMask)], action: V) -> impl Iterator<Item = ((KeyButMask, Keycode), V)> + 'a {
+    grabs.iter().map(move |(keycode, modifiers)| ((from_mod_mask(*modifiers), *keycode), action.clone()))
+}
+
+/// The hotkey modifiers with and without CapsLock (lock) and NumLock (mod2), so that the hotkey works regardless of their state
+fn modifier_combinations(base: ModMask, strict: bool) -> Vec<ModMask> {
+    if strict {
+        return vec![base];
+    }
+    [ModMask::empty(), ModMask::N2, ModMask::LOCK, ModMask::N2 | ModMask::LOCK].iter()
+        .map(|lock_modifiers| base | *lock_modifiers)
+        .fold(Vec::new(), |mut combinations, modifiers| {
+            if !combinations.contains(&modifiers) {
+                combinations.push(modifiers);
+            }
+            combinations
+        })
+}
+
+fn from_mod_mask(modifiers: ModMask) -> KeyButMask {
+    KeyButMask::from_bits_truncate(modifiers.bits())
+}
+
+fn get_keyboard_mapping_reverse(x_conn: &Connection) -> Result<HashMap<Keysym, Vec<Keycode>>, PttError> {
+    let first_keycode = 8;
+    let cookie = x_conn.send_request(&GetKeyboardMapping {
+        first_keycode,
+        count: 248,
+    });
+    let reply = x_conn.wait_for_reply(cookie).map_err(|e| PttError::X11Request("get keyboard mapping", e))?;
+    let mut map: HashMap<Keysym, Vec<Keycode>> = HashMap::new();
+    reply.keysyms()
+        .chunks_exact(reply.keysyms_per_keycode().into())
+        .zip(first_keycode..)
+        .for_each(|(keysyms, keycode)| {
+            let mut keysyms = keysyms.to_vec();
+            keysyms.sort_unstable();
+            keysyms.dedup();
+            keysyms.iter()
+                .filter(|keysym| **keysym != 0)
+                .for_each(|keysym| map.entry(*keysym).or_default().push(keycode))
+        });
+    Ok(map)
+}
+
+fn get_modifier_mapping(x_conn: &Connection) -> Result<HashMap<Keycode, ModMask>, PttError> {
+    let cookie = x_conn.send_request(&GetModifierMapping {});
+    let reply = x_conn.wait_for_reply(cookie).map_err(|e| PttError::X11Request("get modifier mapping", e))?;
+    let keycodes_per_modifier = reply.keycodes().len() / 8;
+    Ok(reply.keycodes()
+        .chunks_exact(keycodes_per_modifier)
+        .zip(&[
+            ModMask::SHIFT,
+            ModMask::LOCK,
+            ModMask::CONTROL,
+            ModMask::N1,
+            ModMask::N2,
+            ModMask::N3,
+            ModMask::N4,
+            ModMask::N5,
+        ])
+        .flat_map(|(keycodes, mask)| keycodes.iter()
+            .filter(|keycode| **keycode != 0)
+            .map(|keycode| (*keycode, *mask)))
+        .collect())
+}
+
+/// The window of the terminal the program runs in, from $WINDOWID which most terminals set, or else the window focused when starting
+fn own_window(x_conn: &Connection) -> Result<Window, PttError> {
+    if let Some(window) = env::var("WINDOWID").ok().and_then(|id| id.parse::<u32>().ok()) {
+        // the X server reports an error if it does not exist
+        return Ok(Window::new(window));
+    }
+    let focus = x_conn.wait_for_reply(x_conn.send_request(&x::GetInputFocus {}))
+        .map_err(|e| PttError::X11Request("get input focus", e))?
+        .focus();
+    if focus.is_none() || x_conn.get_setup().roots().any(|screen| screen.root() == focus) {
+        return Err(PttError::NoFocusWindow);
+    }
+    Ok(focus)
+}
+
+/// Asks for the focus events of the window, for --auto-mute-on-focus-loss
+fn watch_focus(x_conn: &Connection, window: Window) -> Result<(), PttError> {
+    x_conn.send_and_check_request(&x::ChangeWindowAttributes { window, value_list: &[x::Cw::EventMask(x::EventMask::FOCUS_CHANGE)] })
+        .map_err(|e| PttError::X11Request("watch the focus", e.into()))
+}
+
+/// Asks for changes of the properties of the root window, returning the atom of the active window property set by the window manager
+fn watch_active_window(x_conn: &Connection, root: Window) -> Result<Atom, PttError> {
+    let atom = x_conn.wait_for_reply(x_conn.send_request(&x::InternAtom { only_if_exists: false, name: b"_NET_ACTIVE_WINDOW" }))
+        .map_err(|e| PttError::X11Request("look up _NET_ACTIVE_WINDOW", e))?
+        .atom();
+    x_conn.send_and_check_request(&x::ChangeWindowAttributes { window: root, value_list: &[x::Cw::EventMask(x::EventMask::PROPERTY_CHANGE)] })
+        .map_err(|e| PttError::X11Request("watch the active window", e.into()))?;
+    Ok(atom)
+}
+
+/// Hands the class of the active window over to the profiles, when it differs from the previous one
+fn active_window_changed(x_conn: &Connection, root: Window, active_window_atom: Atom, previous_class: &mut Option<String>, expected_capture_state: &ExpectedCaptureState) {
+    let class = match active_window_class(x_conn, root, active_window_atom) {
+        Ok(class) => class.unwrap_or_default(),
+        Err(e) => {
+            debug!("Failed to get the class of the active window: {}", e);
+            return;
+        }
+    };
+    if previous_class.as_ref() != Some(&class) {
+        debug!("Active window class {}", class);
+        *previous_class = Some(class.clone());
+        profile::window_class_changed(class, expected_capture_state);
+    }
+}
+
+/// The class of the active window, the second string of WM_CLASS, or `None` if there is no active window or it has no class
+fn active_window_class(x_conn: &Connection, root: Window, active_window_atom: Atom) -> xcb::Result<Option<String>> {
+    let reply = x_conn.wait_for_reply(x_conn.send_request(&x::GetProperty { delete: false, window: root, property: active_window_atom, r#type: x::ATOM_WINDOW, long_offset: 0, long_length: 1 }))?;
+    let Some(window) = reply.value::<Window>().first().copied().filter(|window| !window.is_none()) else {
+        return Ok(None);
+    };
+    let reply = x_conn.wait_for_reply(x_conn.send_request(&x::GetProperty { delete: false, window, property: x::ATOM_WM_CLASS, r#type: x::ATOM_STRING, long_offset: 0, long_length: 256 }))?;
+    // the instance and the class, each terminated by a zero byte
+    Ok(reply.value::<u8>().split(|byte| *byte == 0).nth(1).map(|class| String::from_utf8_lossy(class).into_owned()))
+}
+
+/// Asks XKB to send a held key as repeated presses without the releases in between, returning whether the X server supports it
+fn enable_detectable_autorepeat(x_conn: &Connection) -> bool {
+    if !x_conn.active_extensions().any(|extension| extension == xcb::Extension::Xkb) {
+        debug!("No XKB, telling autorepeat apart by the event times");
+        return false;
+    }
+    let enabled = x_conn.wait_for_reply(x_conn.send_request(&xcb::xkb::UseExtension { wanted_major: 1, wanted_minor: 0 }))
+        .and_then(|_| x_conn.wait_for_reply(x_conn.send_request(&xcb::xkb::PerClientFlags {
+            device_spec: xcb::xkb::Id::UseCoreKbd as xcb::xkb::DeviceSpec,
+            change: xcb::xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+            value: xcb::xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+            ctrls_to_change: xcb::xkb::BoolCtrl::empty(),
+            auto_ctrls: xcb::xkb::BoolCtrl::empty(),
+            auto_ctrls_values: xcb::xkb::BoolCtrl::empty(),
+        })))
+        .is_ok_and(|reply| reply.value().contains(xcb::xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT));
+    if !enabled {
+        debug!("Detectable autorepeat not supported, telling autorepeat apart by the event times");
+    }
+    enabled
+}
+
+/// Connects to the given display, or to $DISPLAY, returning the root windows of all its screens with the default screen first
+pub fn open_x(display: Option<&str>) -> Result<(Connection, Vec<Window>), PttError> {
+    // XInput2 is only needed for mouse buttons, and XKB for detectable autorepeat and --led
+    let optional_extensions = [xcb::Extension::Input, xcb::Extension::Xkb];
+    let (x_conn, screen_num) = Connection::connect_with_extensions(display, &[], &optional_extensions)
+        .map_err(|source| {
+            let display = display.map(String::from).or_else(|| env::var("DISPLAY").ok()).unwrap_or_default();
+            PttError::X11Connect { display, source }
+        })?;
+    let default_root = x_conn.get_setup().roots().nth(screen_num as usize).ok_or(PttError::NoScreen)?.root();
+    // with separate screens like :0.0 and :0.1, the hotkeys must be grabbed on each, or they only work while the pointer is on one
+    let roots = [default_root].into_iter()
+        .chain(x_conn.get_setup().roots().map(|screen| screen.root()).filter(|root| *root != default_root))
+        .collect();
+    Ok((x_conn, roots))
+}
+
+fn get_keycodes_for_keysym(x_conn: &Connection, keyboard_mapping: &mut Option<HashMap<Keysym, Vec<Keycode>>>, keycodes: &[Keycode], keysym: Option<String>) -> Result<Vec<Keycode>, PttError> {
+    if let Some(keysym_name) = keysym {
+        if keyboard_mapping.is_none() {
+            let map = get_keyboard_mapping_reverse(x_conn)?;
+            *keyboard_mapping = Some(map);
+        }
+        resolve_keysym(keyboard_mapping.as_ref().unwrap(), &keysym_name)
+    } else {
+        // keycode 0 disables the hotkey
+        Ok(keycodes.iter().copied().filter(|keycode| *keycode != 0).collect())
+    }
+}
+
+fn resolve_keysym(keyboard_mapping: &HashMap<Keysym, Vec<Keycode>>, keysym_name: &str) -> Result<Vec<Keycode>, PttError> {
+    let keysym = xkb::Keysym::from_str(keysym_name).map_err(|_| PttError::UnknownKeysym { keysym: keysym_name.to_string(), suggestions: suggest_keysyms(keyboard_mapping, keysym_name) })?;
+    let keycodes = keyboard_mapping.get(&keysym.into()).ok_or_else(|| PttError::KeysymNotFound { keysym: keysym.to_string(), suggestions: suggest_keysyms(keyboard_mapping, keysym_name) })?.to_vec();
+    info!("Keysym '{}' is bound to keycode{} {:?}", keysym, if keycodes.len() == 1 { "" } else { "s" }, keycodes);
+    Ok(keycodes)
+}
+
+/// Lists a few keysyms of the current keyboard mapping with names resembling the given one
+fn suggest_keysyms(keyboard_mapping: &HashMap<Keysym, Vec<Keycode>>, name: &str) -> String {
+    let name = name.to_lowercase();
+    let mut candidates = keyboard_mapping.keys()
+        .map(|keysym| xkb::Keysym::from(*keysym).to_string())
+        .filter_map(|candidate| {
+            let lowercase = candidate.to_lowercase();
+            let distance = if lowercase == name {
+                0
+            } else if lowercase.contains(&name) || name.contains(&lowercase) {
+                1
+            } else {
+                1 + edit_distance(&lowercase, &name)
+            };
+            (distance <= 3).then_some((distance, candidate))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    candidates.dedup();
+    if candidates.is_empty() {
+        return String::new();
+    }
+    let names = candidates.into_iter().take(5).map(|(_, candidate)| candidate).collect::<Vec<_>>();
+    format!(" — similar keysyms on this keyboard: {}", names.join(", "))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous_row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous_row[j] + usize::from(a_char != *b_char);
+            row.push(substitution.min(previous_row[j + 1] + 1).min(row[j] + 1));
+        }
+        previous_row = row;
+    }
+    previous_row[b.len()]
+}
+
+fn grab_hotkeys(grabs: &[(Keycode, ModMask)], x_conn: &Connection, roots: &[Window]) -> Result<(), PttError> {
+    let root_grabs = roots.iter().flat_map(|root| grabs.iter().map(move |grab| (*root, grab))).collect::<Vec<_>>();
+    #[allow(clippy::needless_collect)]
+    let grab_cookies = root_grabs.iter().map(|(root, (keycode, modifiers))| x_conn.send_request_checked(&GrabKey {
+        owner_events: true,
+        grab_window: *root,
+        modifiers: *modifiers,
+        key: *keycode,
+        pointer_mode: GrabMode::Async,
+        keyboard_mode: GrabMode::Async,
+    })).collect::<Vec<_>>();
+
+    let errors = grab_cookies.into_iter()
+        .zip(root_grabs)
+        .filter_map(|(cookie, (_, grab))| x_conn.check_request(cookie).err().map(|e| (grab, e)))
+        .collect::<Vec<_>>();
+    if let Some(((keycode, modifiers), e)) = errors.first() {
+        // don't leave some of the combinations grabbed, on any of the screens
+        ungrab_hotkeys(grabs, x_conn, roots);
+        if let xcb::ProtocolError::X(x::Error::Access(_), _) = e {
+            debug!("Grabbing the hotkey failed: {:?}", e);
+            return Err(PttError::HotkeyTaken { keycode: *keycode, modifiers: *modifiers });
+        }
+        return Err(PttError::HotkeyGrab(format!("Failed to grab hotkey: {:?}", e)));
+    }
+    Ok(())
+}
+
+fn ungrab_hotkeys(grabs: &[(Keycode, ModMask)], x_conn: &Connection, roots: &[Window]) {
+    for root in roots {
+        for (keycode, modifiers) in grabs {
+            x_conn.send_request(&UngrabKey {
+                key: *keycode,
+                grab_window: *root,
+                modifiers: *modifiers,
+            });
+        }
+    }
+    // errors would show up with the next request
+    let _ = x_conn.flush();
+}
+
+fn require_xinput2(x_conn: &Connection, needed_for: &'static str) -> Result<(), PttError> {
+    let version = x_conn.wait_for_reply(x_conn.send_request(&xinput::XiQueryVersion {
+        major_version: 2,
+        minor_version: 0,
+    }))?;
+    if version.major_version() < 2 {
+        return Err(PttError::NoXInput2(needed_for));
+    }
+    Ok(())
+}
+
+/// Gets the raw key events of all keyboards, which other applications keep getting as well
+///
+/// Raw events do not depend on the window with the focus, so selecting them on one root window is enough.
+fn select_raw_key_events(x_conn: &Connection, win: Window) -> Result<(), PttError> {
+    require_xinput2(x_conn, "--passive")?;
+    x_conn.send_and_check_request(&xinput::XiSelectEvents {
+        window: win,
+        masks: &[xinput::EventMaskBuf::new(xinput::Device::AllMaster, &[xinput::XiEventMask::RAW_KEY_PRESS | xinput::XiEventMask::RAW_KEY_RELEASE])],
+    })?;
+    Ok(())
+}
+
+/// The ids and names of the physical keyboards
+fn query_keyboards(x_conn: &Connection) -> Result<Vec<(u16, String)>, PttError> {
+    let reply = x_conn.wait_for_reply(x_conn.send_request(&xinput::XiQueryDevice { device: xinput::Device::All }))?;
+    Ok(reply.infos()
+        .filter(|info| info.r#type() == xinput::DeviceType::SlaveKeyboard)
+        .map(|info| (info.device().id(), info.name().to_utf8().into_owned()))
+        .collect())
+}
+
+fn list_xinput_devices(x_conn: &Connection) -> Result<(), PttError> {
+    require_xinput2(x_conn, "--input-device")?;
+    let keyboards = query_keyboards(x_conn)?;
+    println!("Keyboards, use the id or a part of the name for --input-device:");
+    println!("   Id  Name");
+    for (id, name) in keyboards {
+        println!("{:5}  {}", id, name);
+    }
+    Ok(())
+}
+
+/// The ids of the keyboards whose id is given, or whose name contains the given text ignoring case
+fn find_input_devices(x_conn: &Connection, name_or_id: &str) -> Result<HashSet<u16>, PttError> {
+    require_xinput2(x_conn, "--input-device")?;
+    let name_or_id_lowercase = name_or_id.to_lowercase();
+    let ids = query_keyboards(x_conn)?.into_iter()
+        .filter(|(id, name)| match name_or_id.parse::<u16>() {
+            Ok(wanted_id) => *id == wanted_id,
+            Err(_) => name.to_lowercase().contains(&name_or_id_lowercase),
+        })
+        .map(|(id, name)| {
+            info!("Using input device {} ({})", name, id);
+            id
+        })
+        .collect::<HashSet<_>>();
+    if ids.is_empty() {
+        return Err(PttError::InputDeviceNotFound(name_or_id.to_string()));
+    }
+    Ok(ids)
+}
+
+fn from_input_device(input_device_ids: &Option<HashSet<u16>>, source: xinput::Device) -> bool {
+    input_device_ids.as_ref().is_none_or(|ids| ids.contains(&source.id()))
+}
+
+/// Whether the key is down in the bit vector of QueryKeymap
+fn key_down(keys: &[u8; 32], keycode: Keycode) -> bool {
+    keys[keycode as usize / 8] & (1 << (keycode % 8)) != 0
+}
+
+/// The modifier state of a raw key event, from the modifier keys held down
+fn raw_key_state(held_keys: &HashSet<Keycode>, modifier_mapping: &HashMap<Keycode, ModMask>) -> KeyButMask {
+    from_mod_mask(held_keys.iter()
+        .filter_map(|keycode| modifier_mapping.get(keycode))
+        .fold(ModMask::empty(), |state, modifier| state | *modifier))
+}
+
+/// Grabs the mouse button using XInput2, since the core protocol only supports buttons 1 to 5
+fn grab_button(modifier_combinations: &[ModMask], button: u32, x_conn: &Connection, roots: &[Window]) -> Result<(), PttError> {
+    require_xinput2(x_conn, "mouse buttons")?;
+
+    let modifiers = modifier_combinations.iter().map(|modifiers| modifiers.bits()).collect::<Vec<_>>();
+    for root in roots {
+        let reply = x_conn.wait_for_reply(x_conn.send_request(&xinput::XiPassiveGrabDevice {
+            time: x::CURRENT_TIME,
+            grab_window: *root,
+            cursor: x::CURSOR_NONE,
+            detail: button,
+            device: xinput::Device::AllMaster,
+            grab_type: xinput::GrabType::Button,
+            grab_mode: xinput::GrabMode22::Async,
+            paired_device_mode: GrabMode::Async,
+            owner_events: xinput::GrabOwner::Owner,
+            mask: &[(xinput::XiEventMask::BUTTON_PRESS | xinput::XiEventMask::BUTTON_RELEASE).bits()],
+            modifiers: &modifiers,
+        }))?;
+        // the reply lists the modifier combinations that could not be grabbed
+        if let Some(failed) = reply.modifiers().first() {
+            for root in roots {
+                x_conn.send_request(&xinput::XiPassiveUngrabDevice {
+                    grab_window: *root,
+                    detail: button,
+                    device: xinput::Device::AllMaster,
+                    grab_type: xinput::GrabType::Button,
+                    modifiers: &modifiers,
+                });
+            }
+            x_conn.flush()?;
+            return Err(PttError::HotkeyGrab(format!("Failed to grab button {} with modifiers {:#x}: {:?}", button, failed.modifiers(), failed.status())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIER_ERROR: &str = "expected modifier: `shift`, `lock`, `control`, `mod1`, `mod2`, `mod3`, `mod4`, or `mod5`";
+    #[test]
+    fn parses_single_modifiers() {
+        let expected = [("shift", 1), ("lock", 2), ("control", 4), ("mod1", 8), ("mod2", 16), ("mod3", 32), ("mod4", 64), ("mod5", 128)];
+        for (name, bits) in expected {
+            assert_eq!(parse_modifier(name).map(|mask| mask.bits()), Ok(bits), "{}", name);
+            assert_eq!(parse_modifiers(name).map(|mask| mask.bits()), Ok(bits), "{}", name);
+        }
+    }
+
+    #[test]
+    fn combines_modifiers() {
+        assert_eq!(parse_modifiers("control+mod3").map(|mask| mask.bits()), Ok(4 | 32));
+        assert_eq!(parse_modifiers("shift+control+mod1+mod4").map(|mask| mask.bits()), Ok(1 | 4 | 8 | 64));
+        // the same modifier twice is no different from once
+        assert_eq!(parse_modifiers("mod3+mod3").map(|mask| mask.bits()), Ok(32));
+    }
+
+    #[test]
+    fn empty_modifiers_are_none() {
+        assert_eq!(parse_modifiers(""), Ok(ModMask::empty()));
+    }
+
+    #[test]
+    fn rejects_unknown_modifiers() {
+        assert_eq!(parse_modifier("hyper"), Err(MODIFIER_ERROR));
+        assert_eq!(parse_modifier(""), Err(MODIFIER_ERROR));
+        assert_eq!(parse_modifiers("control+hyper"), Err(MODIFIER_ERROR));
+        assert_eq!(parse_modifiers("control+"), Err(MODIFIER_ERROR));
+        assert_eq!(parse_modifiers("control mod3"), Err(MODIFIER_ERROR));
+    }
+
+    #[test]
+    fn modifiers_are_case_sensitive() {
+        assert_eq!(parse_modifier("Shift"), Err(MODIFIER_ERROR));
+        assert_eq!(parse_modifier("CONTROL"), Err(MODIFIER_ERROR));
+        assert_eq!(parse_modifiers("control+Mod3"), Err(MODIFIER_ERROR));
+    }
+
+    #[test]
+    fn formatted_modifiers_parse_back() {
+        assert_eq!(format_modifiers(ModMask::CONTROL | ModMask::N3), "control+mod3");
+        assert_eq!(format_modifiers(ModMask::empty()), "");
+        let all = ModMask::SHIFT | ModMask::LOCK | ModMask::CONTROL | ModMask::N1 | ModMask::N2 | ModMask::N3 | ModMask::N4 | ModMask::N5;
+        assert_eq!(parse_modifiers(&format_modifiers(all)), Ok(all));
+    }
+}
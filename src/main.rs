@@ -1,396 +1,614 @@
-use std::{fmt, thread};
-use std::collections::HashMap;
-use std::error::Error;
-use std::fmt::{Debug, Display};
-use std::hash::Hash;
-use std::str::FromStr;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Duration, Instant};
-
-use alsa::Mixer;
-use alsa::mixer::{Selem, SelemChannelId, SelemId};
-use chrono::Local;
-use clap::Parser;
-use xcb::Connection;
-use xcb::x::{Event, GetKeyboardMapping, GetModifierMapping, GrabKey, GrabMode, KeyButMask, Keycode, Keysym, ModMask, Window};
+use std::{env, io, process};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+use push_to_talk::{list_cards_and_controls, list_controls, list_devices, list_input_devices, log_error, parse_hotkey_spec, parse_modifiers, AudioBackend, Config, ExitState, Hotkey, InputBackend, Method, Mode, ResumeState};
+#[cfg(feature = "led")]
+use push_to_talk::LockLed;
+use push_to_talk::error::PttError;
+use push_to_talk::mixer::ControlId;
+use push_to_talk::profile::Profile;
+use push_to_talk::state_file::StateFileFormat;
+use push_to_talk::state_reporter::StateReporter;
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::time::ChronoLocal;
+use xcb::x::{Keycode, ModMask};
+
+mod config;
+mod setup;
+
+const SIGNALS_HELP: &str = "Signals:
+  SIGUSR1           mute, like the --mute-key
+  SIGUSR2           unmute, like the --unmute-key but after the --signal-unmute-delay
+  SIGINT, SIGTERM   leave the --exit-state and exit";
 
 /// Push to talk using X11 hotkey — uses ALSA but works indirectly also with PulseAudio and PipeWire
-#[derive(Parser, Clone, Debug)]
-#[clap(author, version, about)]
+#[derive(clap::Parser, Clone, Debug)]
+#[clap(author, version, about, after_help = SIGNALS_HELP)]
 struct Args {
-    /// alsa device name
-    #[clap(short, long, default_value_t = String::from("default"))]
-    device: String,
+    /// alsa device name, or source name with --audio-backend pulse, give several times to mute several devices together
+    #[clap(short, long, default_value = "default", action = clap::ArgAction::Append)]
+    device: Vec<String>,
 
-    /// alsa mixer control name
-    #[clap(short, long, default_value_t = String::from("Capture"))]
-    control: String,
+    /// how to mute the devices: with a control of the alsa mixer, or as PulseAudio (or PipeWire) sources through pactl
+    #[clap(long, value_enum, default_value_t = AudioBackend::Alsa)]
+    audio_backend: AudioBackend,
+
+    /// alsa mixer control name, or auto to use the only control with a capture switch (or capture volume with --method volume); give once for all devices or once per device, use , for several controls muted together e.g. "Mic,Mic Boost", and a number after a name for its index e.g. "Capture,1"
+    #[clap(short, long, default_value = "auto", action = clap::ArgAction::Append)]
+    control: Vec<String>,
+
+    /// index of the --control names not followed by one, for controls sharing a name
+    #[clap(long, default_value_t = 0)]
+    control_index: u32,
+
+    /// how to mute the control: its capture switch, or its capture volume which is set to the minimum and back; controls without a capture switch always use their capture volume
+    #[clap(long, value_enum, default_value_t = Method::Switch)]
+    method: Method,
+
+    /// shorthand for --method volume
+    #[clap(long, conflicts_with = "method")]
+    use_volume: bool,
+
+    /// capture volume to set when unmuting, in percent of the range of the control; with --method volume instead of restoring the previous volume
+    #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    capture_volume: Option<u8>,
+
+    /// capture volume to set when muting, in percent of the range of the control, with --method volume or --capture-volume
+    #[clap(long, value_name = "PERCENT", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    muted_volume: u8,
+
+    /// with --method switch, put back the capture volume of each channel from before muting when unmuting, and whenever it changes while unmuted, for drivers that reset it along with the switch
+    #[clap(long, conflicts_with = "capture_volume")]
+    restore_volume: bool,
+
+    /// alsa mixer control of the first device whose playback switch is turned off while the microphone is unmuted, e.g. Master to mute the speakers while talking
+    #[clap(long)]
+    playback_control: Option<String>,
 
     /// delay unmute by this much time (milliseconds)
     #[clap(short, long, default_value_t = 150)]
     unmute_delay: u64,
 
+    /// delay mute on push hotkey release by this much time to not cut off the last syllable (milliseconds), pressing the hotkey again meanwhile keeps it unmuted; unlike the unmute delay it is not applied to the toggle hotkey
+    #[clap(short, long, alias = "mute-delay", default_value_t = 0)]
+    remute_delay: u64,
+
+    /// hold back the hotkey events until none arrived for this long, then act once on the state a burst of presses and releases from a chattering switch ends in (milliseconds)
+    #[clap(long, value_name = "MS", default_value_t = 0)]
+    debounce: u64,
+
     /// modifiers for push hotkey, use + for multiple e.g. control+mod3
     #[clap(short='m', long, default_value = "mod3", value_parser(parse_modifiers))]
     push_modifiers: ModMask,
 
-    /// keycode for push hotkey (62 = Left Shift)
-    #[clap(short='c', long, default_value_t = 62, group="push-key")]
-    push_keycode: Keycode,
+    /// keycodes for push hotkey, use , for multiple e.g. 62,108 (62 = Left Shift, 0 to disable)
+    #[clap(short='k', long, default_value = "62", value_delimiter = ',', group="push-key")]
+    push_keycode: Vec<Keycode>,
 
-    /// modifiers for push hotkey (62 = Left Shift)
-    #[clap(short='s', long, group="push-key")]
+    /// keysym for push hotkey ("Shift_R"), grabs all keycodes producing it
+    #[clap(short='s', long, aliases = ["hotkey-key", "hotkey-keysym", "keysym"], group="push-key")]
     push_keysym: Option<String>,
 
+    /// additional push hotkey as [modifiers:]keycode or [modifiers:]keysym e.g. mod3:62, mod3:Shift_L or 0:F13 for no modifiers, can be repeated
+    #[clap(long, alias = "hotkey", value_name = "HOTKEY", value_parser(parse_hotkey_spec), action = clap::ArgAction::Append)]
+    push_hotkey: Vec<Hotkey>,
+
+    /// hotkey that always unmutes, as [modifiers:]keycode or [modifiers:]keysym like --push-hotkey, can be repeated
+    #[clap(long, value_name = "HOTKEY", value_parser(parse_hotkey_spec), action = clap::ArgAction::Append)]
+    unmute_key: Vec<Hotkey>,
+
+    /// hotkey that always mutes, as [modifiers:]keycode or [modifiers:]keysym like --push-hotkey, can be repeated
+    #[clap(long, value_name = "HOTKEY", value_parser(parse_hotkey_spec), action = clap::ArgAction::Append)]
+    mute_key: Vec<Hotkey>,
+
+    /// hotkey that mutes and ignores all other hotkeys until pressed again, as [modifiers:]keycode or [modifiers:]keysym like --push-hotkey
+    #[clap(long, value_name = "HOTKEY", value_parser(parse_hotkey_spec))]
+    panic_key: Option<Hotkey>,
+
+    /// mouse button for push hotkey together with the push modifiers, e.g. 8 or 9 for thumb buttons
+    #[clap(short='b', long, alias = "hotkey-button")]
+    push_button: Option<u32>,
+
+    /// allow --push-button without push modifiers, which takes the button away from all other applications
+    #[clap(long)]
+    allow_bare_button: bool,
+
     /// modifiers for toggle hotkey, use + for multiple e.g. control+mod3
     #[clap(short='M', long, default_value = "mod3+control", value_parser(parse_modifiers))]
     toggle_modifiers: ModMask,
 
-    /// keycode for toggle hotkey (62 = Left Shift, 0 to disable)
-    #[clap(short='C', long, default_value_t = 62, group="toggle-key")]
-    toggle_keycode: Keycode,
+    /// keycodes for toggle hotkey, use , for multiple (62 = Left Shift, 0 to disable)
+    #[clap(short='C', long, default_value = "62", value_delimiter = ',', group="toggle-key")]
+    toggle_keycode: Vec<Keycode>,
 
-    /// keysym for toggle hotkey ("Shift_L")
+    /// keysym for toggle hotkey ("Shift_L"), grabs all keycodes producing it
     #[clap(short='S', long, group="toggle-key")]
     toggle_keysym: Option<String>,
-}
 
-fn main() {
-    let args: Args = Args::parse();
+    /// only react to the hotkeys with exactly the given modifiers, by default CapsLock (lock) and NumLock (mod2) are ignored
+    #[clap(long)]
+    strict_modifiers: bool,
 
-    let expected_capture_state = Arc::new(AtomicBool::new(false));
+    /// --ignore-lock-modifiers=false is the same as --strict-modifiers
+    #[clap(long, value_name = "BOOL", action = clap::ArgAction::Set, num_args = 0..=1, default_value_t = true, default_missing_value = "true", hide_possible_values = true)]
+    ignore_lock_modifiers: bool,
 
-    {
-        let expected_capture_state = expected_capture_state.clone();
-        let args = args.clone();
-        thread::spawn(move || {
-            enforce_mixer_capture_state(expected_capture_state, &args.device, &args.control)
-        });
-    }
+    /// what the push hotkey does: hold to talk, or press once to talk and again to stop
+    #[clap(long, value_enum, default_value_t = Mode::Push)]
+    mode: Mode,
 
-    listen_to_keyboard_events_and_update_mixer(expected_capture_state, &args.device, &args.control, args.unmute_delay,
-                                               args.push_modifiers, args.push_keycode, args.push_keysym,
-                                               args.toggle_modifiers, args.toggle_keycode, args.toggle_keysym)
-}
+    /// shorthand for --mode toggle
+    #[clap(short, long, conflicts_with = "mode")]
+    toggle: bool,
 
-fn parse_modifiers(str: &str) -> Result<ModMask, &'static str> {
-    if !str.is_empty() {
-        str.split('+')
-            .map(parse_modifier)
-            .try_fold(ModMask::empty(), |prev, x| x.map(|cur| prev | cur))
-    } else {
-        Ok(ModMask::empty())
-    }
-}
+    /// in push mode, a tap of the push hotkey shorter than this keeps it pressed until the next press (milliseconds, 0 to disable)
+    #[clap(long, default_value_t = 0)]
+    tap_toggle_threshold: u32,
 
-fn parse_modifier(str: &str) -> Result<ModMask, &'static str> {
-    match str {
-        "shift" => Ok(ModMask::SHIFT),
-        "lock" => Ok(ModMask::LOCK),
-        "control" => Ok(ModMask::CONTROL),
-        "mod1" => Ok(ModMask::N1),
-        "mod2" => Ok(ModMask::N2),
-        "mod3" => Ok(ModMask::N3),
-        "mod4" => Ok(ModMask::N4),
-        "mod5" => Ok(ModMask::N5),
-        _ => Err("expected modifier: `shift`, `lock`, `control`, `mod1`, `mod2`, `mod3`, `mod4`, or `mod5`"),
-    }
-}
+    /// in push mode, pressing the push hotkey twice within this time keeps it pressed until the next press (milliseconds, 0 to disable)
+    #[clap(long, default_value_t = 0)]
+    double_tap_lock: u32,
 
-// -------------
+    /// in push mode, releasing the push hotkey before holding it this long never unmutes; the earliest unmute is the longer of this and the unmute delay (milliseconds, 0 to disable)
+    #[clap(long, value_name = "MS", default_value_t = 0)]
+    min_hold: u32,
 
-fn enforce_mixer_capture_state(expected_capture_state: Arc<AtomicBool>, device: &str, control: &str) -> ! {
-    let mut alsa_mixer = Mixer::new(device, false).expect("Failed to setup alsa");
-    loop {
-        let result = get_alsa_mixer_capture_elem(&alsa_mixer, control);
-        if result.is_err() {
-            thread::sleep(Duration::from_millis(10));
-            continue;
-        }
-        let mixer_capture_elem = result.expect("Failed to find recording channel");
-        let actual = get_unanimous_capture_state(&mixer_capture_elem).expect("Could not get capture switch value");
-        let expected = expected_capture_state.load(Ordering::Acquire);
-        if actual != Some(expected) {
-            println!("{} Fixing capture state to {}", log_timestamp(), if expected { "unmuted" } else { "muted" });
-            if let Err(e) = set_capture_state(&mixer_capture_elem, expected) {
-                println!("{} - Error fixing: {:?}", log_timestamp(), e);
-            }
-        }
-        let before = Instant::now();
-        alsa_mixer.wait(None).expect("alsa_mixer.wait() failed");
-        alsa_mixer.handle_events().expect("alsa_mixer.handle_events() failed");
-        if Instant::now().duration_since(before) > Duration::from_millis(1000) {
-            alsa_mixer = Mixer::new(device, false).expect("Failed to setup alsa");
-        }
-    }
-}
+    /// mute once unmuted without a break for this long, in case a key got stuck or the microphone was left on, also in toggle mode (milliseconds, no default)
+    #[clap(long, value_name = "MS")]
+    max_unmute_ms: Option<u64>,
 
-fn get_alsa_mixer_capture_elem<'a>(alsa_mixer: &'a Mixer, control: &str) -> Result<Selem<'a>, Box<dyn Error>> {
-    let mixer_capture_elem = alsa_mixer.find_selem(&SelemId::new(control, 0)).ok_or_else(|| GenericError(format!("Could not find simple control {}", control)))?;
-    if !mixer_capture_elem.has_capture_switch() {
-        return Err(GenericError("Capture switch not found, cannot adjust").into());
-    }
-    Ok(mixer_capture_elem)
-}
+    /// --max-unmute-ms in seconds
+    #[clap(long, value_name = "SECONDS", conflicts_with = "max_unmute_ms")]
+    max_talk: Option<u64>,
 
-fn get_unanimous_capture_state(mixer_capture_elem: &Selem<'_>) -> Result<Option<bool>, Box<dyn Error>> {
-    let mut channels = SelemChannelId::all().iter();
-    let first_channel_state = 0 != mixer_capture_elem.get_capture_switch(*channels.next().unwrap())?;
-    for channel in channels {
-        let state = 0 != mixer_capture_elem.get_capture_switch(*channel)?;
-        if state != first_channel_state {
-            return Ok(None)
-        }
-    }
-    Ok(Some(first_channel_state))
-}
+    /// in toggle mode or while locked open, mute after this many seconds without pressing any hotkey (0 to disable)
+    #[clap(long, value_name = "SECONDS", default_value_t = 0)]
+    auto_mute_idle: u64,
 
-fn set_capture_state(mixer_capture_elem: &Selem<'_>, state: bool) -> Result<(), Box<dyn Error>> {
-    for channel in SelemChannelId::all() {
-        mixer_capture_elem.set_capture_switch(*channel, state.into())?;
-    }
-    Ok(())
-}
+    /// push-to-mute: start unmuted and mute while the push hotkey is held, unmute delay applies when returning to unmuted; with --mode toggle just start unmuted
+    #[clap(long, alias = "push-to-mute")]
+    invert: bool,
 
-// -------------
+    /// play a short high tone when unmuting and a low one when muting
+    #[clap(long)]
+    beep: bool,
 
-#[allow(clippy::too_many_arguments)]
-fn listen_to_keyboard_events_and_update_mixer(expected_capture_state: Arc<AtomicBool>, device: &str, control: &str, unmute_delay_ms: u64, push_modifiers: ModMask, push_keycode: Keycode, push_keysym: Option<String>, toggle_modifiers: ModMask, toggle_keycode: Keycode, toggle_keysym: Option<String>) {
-    let alsa_mixer = Mixer::new(device, false).expect("Failed to setup alsa");
-    let mixer_capture_elem = get_alsa_mixer_capture_elem(&alsa_mixer, control).expect("Failed to find recording channel");
-    let (x_conn, win) = open_x().expect("Failed to setup X11");
+    /// WAV file to play when unmuting, instead of the --beep tone
+    #[clap(long, value_name = "WAV_FILE")]
+    sound_on: Option<PathBuf>,
 
-    let mut keyboard_mapping = None;
+    /// WAV file to play when muting, instead of the --beep tone
+    #[clap(long, value_name = "WAV_FILE")]
+    sound_off: Option<PathBuf>,
 
-    let push_keycodes = get_keycodes_for_keysym(&x_conn, &mut keyboard_mapping, push_keycode, push_keysym).unwrap();
-    let toggle_keycodes = get_keycodes_for_keysym(&x_conn, &mut keyboard_mapping, toggle_keycode, toggle_keysym).unwrap();
+    /// play a reminder tone every this many seconds while unmuted without a break, e.g. in case the microphone was locked open and forgotten (no default)
+    #[clap(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    open_mic_reminder: Option<u64>,
 
-    drop(keyboard_mapping);
+    /// WAV file to play for --open-mic-reminder, instead of the tone
+    #[clap(long, value_name = "WAV_FILE", requires = "open_mic_reminder")]
+    reminder_sound: Option<PathBuf>,
 
-    listen_to_hotkey(push_modifiers, &push_keycodes, &x_conn, win).unwrap();
-    listen_to_hotkey(toggle_modifiers, &toggle_keycodes, &x_conn, win).unwrap();
+    /// alsa playback device for --beep, --sound-on, --sound-off and --open-mic-reminder [default: same as --device]
+    #[clap(long, value_name = "DEVICE", alias = "sound-device")]
+    beep_device: Option<String>,
 
-    // in case keycode is a modifier we need to have adjusted modifiers for release events
-    let keycode_to_modifier = get_modifier_mapping(&x_conn).unwrap();
+    /// react to every key release and press produced by keyboard autorepeat, by default they are skipped
+    #[clap(long)]
+    no_repeat_filter: bool,
 
-    let push_release_modifiers = push_keycodes.iter().map(|keycode| push_modifiers | keycode_to_modifier.get(keycode).cloned().unwrap_or_else(ModMask::empty));
-    let toggle_release_modifiers = toggle_keycodes.iter().map(|keycode| toggle_modifiers | keycode_to_modifier.get(keycode).cloned().unwrap_or_else(ModMask::empty));
+    /// mute right away when the terminal running the program loses the focus, e.g. when switching to another application
+    #[clap(long)]
+    auto_mute_on_focus_loss: bool,
 
-    #[derive(Debug, Clone)]
-    enum KeyAction {
-        Push,
-        Toggle,
-    }
+    /// do not check every 2 seconds while unmuted that the held push key is still down, muting if its release got lost
+    #[clap(long)]
+    no_stuck_key_check: bool,
 
-    let push_press_entries = push_keycodes.iter().map(|keycode| ((from_mod_mask(push_modifiers), *keycode), KeyAction::Push));
-    let toggle_press_entries = toggle_keycodes.iter().map(|keycode| ((from_mod_mask(toggle_modifiers), *keycode), KeyAction::Toggle));
-    let push_release_entries = push_keycodes.iter().zip(push_release_modifiers).map(|(keycode, modifiers)| ((from_mod_mask(modifiers), *keycode), KeyAction::Push));
-    let toggle_release_entries = toggle_keycodes.iter().zip(toggle_release_modifiers).map(|(keycode, modifiers)| ((from_mod_mask(modifiers), *keycode), KeyAction::Toggle));
-
-    let press_map = try_collect_map(push_press_entries.chain(toggle_press_entries)).unwrap();
-    let release_map = try_collect_map(push_release_entries.chain(toggle_release_entries)).unwrap();
-
-    // don't immediately unmute on release after muting on press
-    let mut mute_pending_release = false;
-
-    let mut next_event_maybe: Option<xcb::Result<xcb::Event>> = None;
-    loop {
-        let event = next_event_maybe.take().unwrap_or_else(|| x_conn.wait_for_event());
-        thread::sleep(Duration::from_millis(15)); // sometimes the next press event of a repeated event arrives some 3..6ms later
-        next_event_maybe = match x_conn.poll_for_event() {
-            Ok(Some(x)) => Some(Ok(x)),
-            Ok(None) => None,
-            Err(e) => Some(Err(e)),
-        };
-        #[allow(unreachable_patterns)]
-            let event = match event {
-            Err(e) => {
-                println!("{} Error, exiting — {:#?}", log_timestamp(), e);
-                break;
-            }
-            Ok(xcb::Event::X(e)) => e,
-            Ok(e) => {
-                println!("{} Unsupported event, exiting — {:#?}", log_timestamp(), e);
-                break;
-            }
-        };
-        match event {
-            Event::KeyPress(evt) => {
-                match press_map.get(&(evt.state(), evt.detail())) {
-                    Some(KeyAction::Push) => {
-                        println!("{} Unmuting by push-press", log_timestamp());
-                        unmute(&expected_capture_state, unmute_delay_ms, &mixer_capture_elem);
-                    },
-                    Some(KeyAction::Toggle) => {
-                        if expected_capture_state.load(Ordering::Acquire) {
-                            println!("{} Muting by toggle-press", log_timestamp());
-                            mute(&expected_capture_state, &mixer_capture_elem);
-                            mute_pending_release = true;
-                        }
-                    },
-                    _ => ()
-                }
-            }
-            Event::KeyRelease(evt) => {
-                // skip repeated key events (e.g. Pause key)
-                if let Some(Ok(xcb::Event::X(Event::KeyPress(press_evt)))) = &next_event_maybe {
-                    if press_evt.detail() == evt.detail() && press_evt.time() == evt.time() {
-                        next_event_maybe = None; // skip both next press event and ..
-                        continue; // current release event
-                    }
-                }
-
-                match release_map.get(&(evt.state(), evt.detail())) {
-                    Some(KeyAction::Push) => {
-                        println!("{} Muting by push-release", log_timestamp());
-                        mute(&expected_capture_state, &mixer_capture_elem);
-                    },
-                    Some(KeyAction::Toggle) => {
-                        if mute_pending_release {
-                            mute_pending_release = false;
-                        } else if !expected_capture_state.load(Ordering::Acquire) {
-                            println!("{} Unmuting by toggle-release", log_timestamp());
-                            unmute(&expected_capture_state, unmute_delay_ms, &mixer_capture_elem);
-                        }
-                    },
-                    _ => ()
-                }
-            }
-            _ => (),
-        }
-        // just in case they otherwise pile up somewhere
-        alsa_mixer.handle_events().expect("alsa_mixer.handle_events() failed");
-    }
-}
+    /// reopen the alsa mixer when it fails, e.g. when a USB microphone is unplugged, and reconnect to the X server when the connection is lost
+    #[clap(long, value_name = "BOOL", action = clap::ArgAction::Set, num_args = 0..=1, default_value_t = true, default_missing_value = "true", hide_possible_values = true)]
+    reconnect: bool,
 
-fn try_collect_map<K: Debug + Eq + Hash, V: Debug, I: Iterator<Item = (K, V)>>(mut entries: I) -> Result<HashMap<K, V>, GenericError<&'static str>> {
-    entries
-        .try_fold(HashMap::new(), |mut map, (k, v)|
-            if map.insert(k, v).is_none() {
-                Ok(map)
-            } else {
-                Err(GenericError("Conflicting keybindings"))
-            })
-}
+    /// exit when the alsa mixer fails or the connection to the X server is lost, the same as --reconnect=false
+    #[clap(long)]
+    no_reconnect: bool,
+
+    /// exit after this many failed attempts in a row to reconnect to the X server [default: unlimited]
+    #[clap(long, value_name = "COUNT", value_parser = clap::value_parser!(u32).range(1..))]
+    x11_reconnect_attempts: Option<u32>,
+
+    /// the capture state to set after resuming from suspend, when the hotkeys are grabbed and the mixers opened again
+    #[clap(long, value_enum, default_value_t = ResumeState::Muted)]
+    resume_state: ResumeState,
+
+    /// exit after this many failed attempts in a row to reopen the alsa mixer [default: unlimited]
+    #[clap(long, value_name = "COUNT")]
+    alsa_retry_limit: Option<u32>,
+
+    /// show a short desktop notification when the microphone gets muted or unmuted
+    #[cfg(feature = "notifications")]
+    #[clap(short = 'n', long, alias = "notifications")]
+    notify: bool,
+
+    /// keyboard LED to light while unmuted, as index from 0 for the first LED (xset led 1)
+    #[cfg(feature = "led")]
+    #[clap(long, value_name = "INDEX", value_parser = clap::value_parser!(u8).range(0..8))]
+    led_on: Option<u8>,
+
+    /// keyboard LED to light while muted, as index from 0 for the first LED (xset led 1)
+    #[cfg(feature = "led")]
+    #[clap(long, value_name = "INDEX", value_parser = clap::value_parser!(u8).range(0..8))]
+    led_off: Option<u8>,
+
+    /// lock LED to light while unmuted using XKB, which changes the actual lock indicator and may confuse other software; turned off when exiting
+    #[cfg(feature = "led")]
+    #[clap(long, value_enum)]
+    led: Option<LockLed>,
+
+    /// the capture state to leave behind when exiting, restore puts it back to how it was when starting
+    #[clap(long, value_enum, default_value_t = ExitState::Muted)]
+    exit_state: ExitState,
+
+    /// shorthand for --exit-state restore
+    #[clap(long, conflicts_with = "exit_state")]
+    restore_on_exit: bool,
+
+    /// check that the arguments and the config file are valid, the mixers can be opened and the X server connected to, and exit without grabbing the hotkeys or changing the mixers
+    #[clap(long)]
+    check: bool,
+
+    /// handle and log the hotkeys as usual but leave the mixers as they are, to try out the hotkeys before trusting them on a call
+    #[clap(long)]
+    dry_run: bool,
+
+    /// don't tell systemd when ready, stopping or ping its watchdog, even if running as a Type=notify service built with the systemd feature
+    #[clap(long)]
+    no_sd_notify: bool,
+
+    /// write the process id to this file, removed again on exit
+    #[clap(long, value_name = "FILE")]
+    pid_file: Option<PathBuf>,
+
+    /// list the alsa sound cards, to find a value for --device, and exit
+    #[clap(long)]
+    list_devices: bool,
+
+    /// list the mixer controls of the alsa device with their capture capabilities, to find a value for --control, and exit
+    #[clap(long)]
+    list_controls: bool,
+
+    /// how to watch the hotkeys: grab them from the X server, or read the keyboards directly with evdev, e.g. under Wayland
+    #[clap(long, value_enum, alias = "backend", default_value_t = InputBackend::X11)]
+    input_backend: InputBackend,
+
+    /// X display to connect to, like :1 [default: $DISPLAY]
+    #[clap(long)]
+    display: Option<String>,
+
+    /// X authority file for connecting to the display, e.g. when running as a service without XAUTHORITY set [default: $XAUTHORITY]
+    #[clap(long, value_name = "FILE")]
+    xauthority: Option<PathBuf>,
+
+    /// only react to hotkeys from this keyboard, given as XInput2 device id or part of its name, implies --passive; with evdev the device like event5 or part of its name
+    #[clap(long, value_name = "NAME_OR_ID")]
+    input_device: Option<String>,
+
+    /// list the XInput2 keyboards, or the evdev ones with --input-backend evdev, to find a value for --input-device, and exit
+    #[clap(long)]
+    list_input_devices: bool,
+
+    /// watch the hotkeys with XInput2 raw key events instead of grabbing them, so that other applications still get the keys as well
+    #[clap(long, alias = "raw-events")]
+    passive: bool,
+
+    /// how many times to try again if another application has grabbed a hotkey
+    #[clap(long, default_value_t = 0)]
+    grab_retries: u32,
+
+    /// wait this long before trying to grab the hotkeys again, doubled for each further try (milliseconds)
+    #[clap(long, default_value_t = 1000)]
+    grab_retry_interval: u64,
+
+    /// if another application has grabbed a hotkey, watch the hotkeys like with --passive instead of exiting
+    #[clap(long)]
+    grab_fallback: bool,
+
+    /// how to print the current state and every change to stdout, logging goes to stderr for other formats than plain
+    #[clap(long, value_enum, default_value_t = StateReporter::Plain)]
+    output: StateReporter,
+
+    /// listen on this Unix socket for the line commands status, mute, unmute and toggle, e.g. for a tray icon
+    #[clap(long, value_name = "PATH")]
+    control_socket: Option<PathBuf>,
+
+    /// offer the D-Bus service io.github.xkr47.PushToTalk on the session bus, with the methods Mute, Unmute and Toggle, the property State and the signal StateChanged
+    #[cfg(feature = "dbus")]
+    #[clap(long)]
+    dbus: bool,
+
+    /// send the state as a byte, 0 for muted and 1 for unmuted, to every client of this Unix socket when connecting and on every change, e.g. for a status bar
+    #[clap(long, value_name = "PATH")]
+    status_socket: Option<PathBuf>,
+
+    /// unmute delay for SIGUSR2, in milliseconds
+    #[clap(long, value_name = "MS", default_value_t = 0)]
+    signal_unmute_delay: u64,
+
+    /// keep the state in this file as muted or unmuted, replacing it on every change and removing it on exit, e.g. for a status bar reading it every second
+    #[clap(long, value_name = "PATH")]
+    state_file: Option<PathBuf>,
+
+    /// how to write the --state-file
+    #[clap(long, value_enum, default_value_t = StateFileFormat::Text)]
+    state_file_format: StateFileFormat,
+
+    /// shorthand for --output json-events
+    #[clap(short, long, conflicts_with = "output")]
+    json_output: bool,
+
+    /// how much to log, overridden by $RUST_LOG if set, e.g. RUST_LOG=debug
+    #[clap(short, long, value_enum, default_value_t = LogLevel::Info)]
+    log_level: LogLevel,
 
-fn from_mod_mask(modifiers: ModMask) -> KeyButMask {
-    KeyButMask::from_bits_truncate(modifiers.bits())
+    /// log one level more than --log-level, can be repeated
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// log one level less than --log-level, can be repeated, e.g. -qq for errors only
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// log how long each change takes from the hotkey event until the mixer confirms it, and a summary when exiting, e.g. to tune --unmute-delay
+    #[clap(long)]
+    measure_latency: bool,
+
+    /// config file with defaults for the above options [default: $XDG_CONFIG_HOME/push-to-talk/config.toml]
+    #[clap(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// the --control values of each --device
+    #[clap(skip)]
+    device_controls: Vec<Vec<ControlId>>,
+
+    /// from the [profiles] of the config file
+    #[clap(skip)]
+    profiles: Vec<Profile>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn get_keyboard_mapping_reverse(x_conn: &Connection) -> Result<HashMap<Keysym, Vec<Keycode>>, Box<dyn Error>> {
-    let first_keycode = 8;
-    let cookie = x_conn.send_request(&GetKeyboardMapping {
-        first_keycode,
-        count: 248,
-    });
-    let reply = x_conn.wait_for_reply(cookie).map_err(|e| GenericError(format!("Failed to get keyboard mapping: {:?}", e)))?;
-    let mut map: HashMap<Keysym, Vec<Keycode>> = HashMap::new();
-    reply.keysyms()
-        .chunks_exact(reply.keysyms_per_keycode().into())
-        .zip(first_keycode..)
-        .for_each(|(keysyms, keycode)| {
-            let mut keysyms = keysyms.to_vec();
-            keysyms.sort_unstable();
-            keysyms.dedup();
-            keysyms.iter()
-                .filter(|keysym| **keysym != 0)
-                .for_each(|keysym| map.entry(*keysym).or_default().push(keycode))
-        });
-    Ok(map)
+#[derive(clap::Subcommand, Clone, Debug)]
+enum Command {
+    /// list all alsa sound cards with their mixer controls, and whether the controls have a capture switch
+    ListDevices,
+    /// print the completion script for the shell to stdout
+    Completions {
+        #[clap(long, value_enum)]
+        shell: Shell,
+    },
+    /// ask for the device, mixer control, hotkey and unmute delay, and write them to the config file
+    Setup {
+        /// take the default device and the automatically detected control without asking, failing if there is a choice to make
+        #[clap(long)]
+        noninteractive: bool,
+    },
 }
 
-fn get_modifier_mapping(x_conn: &Connection) -> Result<HashMap<Keycode, ModMask>, Box<dyn Error>> {
-    let cookie = x_conn.send_request(&GetModifierMapping {});
-    let reply = x_conn.wait_for_reply(cookie).map_err(|e| GenericError(format!("Failed to get modifier mapping: {:?}", e)))?;
-    let keycodes_per_modifier = reply.keycodes().len() / 8;
-    Ok(reply.keycodes()
-        .chunks_exact(keycodes_per_modifier)
-        .zip(&[
-            ModMask::SHIFT,
-            ModMask::LOCK,
-            ModMask::CONTROL,
-            ModMask::N1,
-            ModMask::N2,
-            ModMask::N3,
-            ModMask::N4,
-            ModMask::N5,
-        ])
-        .flat_map(|(keycodes, mask)| keycodes.iter()
-            .filter(|keycode| **keycode != 0)
-            .map(|keycode| (*keycode, *mask)))
-        .collect())
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
 
-fn mute(expected_capture_state: &Arc<AtomicBool>, mixer_capture_elem: &Selem) {
-    set_expected_capture_state(expected_capture_state, mixer_capture_elem, false);
+impl LogLevel {
+    /// Moved up by --verbose and down by --quiet, as far as the levels go
+    fn adjusted(self, verbose: u8, quiet: u8) -> LogLevel {
+        let levels = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+        let index = levels.iter().position(|level| *level == self).unwrap_or_default() as i32 + verbose as i32 - quiet as i32;
+        levels[index.clamp(0, levels.len() as i32 - 1) as usize]
+    }
 }
 
-fn unmute(expected_capture_state: &Arc<AtomicBool>, unmute_delay_ms: u64, mixer_capture_elem: &Selem) {
-    thread::sleep(Duration::from_millis(unmute_delay_ms));
-    set_expected_capture_state(expected_capture_state, mixer_capture_elem, true);
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Level {
+        match level {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
 }
 
-fn open_x() -> Result<(Connection, Window), Box<dyn Error>> {
-    let (x_conn, screen_num) = Connection::connect(None)?;
-    let screen = x_conn.get_setup().roots().nth(screen_num as usize).ok_or(GenericError("Could not find screen"))?;
-    let root = screen.root();
-    Ok((x_conn, root))
+fn main() {
+    let args: Args = config::load_args();
+    init_logging(args.log_level.adjusted(args.verbose, args.quiet), args.output.uses_stdout());
+    #[cfg(feature = "systemd")]
+    let sd_notify = !args.no_sd_notify;
+    if let Err(e) = run(args) {
+        log_error(&e);
+        #[cfg(feature = "systemd")]
+        if sd_notify {
+            push_to_talk::systemd::stopping();
+        }
+        process::exit(1);
+    }
 }
 
-fn get_keycodes_for_keysym(x_conn: &Connection, keyboard_mapping: &mut Option<HashMap<Keysym, Vec<Keycode>>>, keycode: Keycode, keysym: Option<String>) -> Result<Vec<Keycode>, Box<dyn Error>> {
-    if let Some(keysym) = keysym {
-        let keysym = xkb::Keysym::from_str(&keysym).map_err(|_| GenericError(format!("Unknown keysym '{}'", keysym)))?;
-        if keyboard_mapping.is_none() {
-            let map = get_keyboard_mapping_reverse(x_conn)?;
-            *keyboard_mapping = Some(map);
+fn run(args: Args) -> Result<(), PttError> {
+    // read by xcb when connecting, set while there is only this thread
+    if let Some(xauthority) = &args.xauthority {
+        env::set_var("XAUTHORITY", xauthority);
+    }
+
+    match args.command {
+        Some(Command::ListDevices) => return list_cards_and_controls().map_err(PttError::ListSoundCards),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Args::command(), env!("CARGO_PKG_NAME"), &mut io::stdout());
+            return Ok(());
         }
-        let keyboard_mapping: &HashMap<Keysym, Vec<Keycode>> = keyboard_mapping.as_ref().unwrap();
-        Ok(keyboard_mapping.get(&keysym.into()).ok_or_else(|| GenericError(format!("No keycode bound to keysym '{}'", keysym)))?.to_vec())
-    } else {
-        Ok(vec![keycode])
+        Some(Command::Setup { noninteractive }) => return setup::run(args.config.as_deref(), args.display.as_deref(), noninteractive),
+        None => (),
     }
+    if args.list_devices {
+        return list_devices().map_err(PttError::ListSoundCards);
+    }
+    if args.list_controls {
+        for device in &args.device {
+            list_controls(device)?;
+        }
+        return Ok(());
+    }
+    if args.list_input_devices {
+        return list_input_devices(args.input_backend, args.display.as_deref());
+    }
+
+    if args.check {
+        return push_to_talk::check(args.into());
+    }
+    push_to_talk::run(args.into())
 }
 
-fn listen_to_hotkey(modifiers: ModMask, keycodes: &[Keycode], x_conn: &Connection, win: Window) -> Result<(), Box<dyn Error>> {
-    #[allow(clippy::needless_collect)]
-    let grab_cookies = keycodes.iter().map(|keycode| x_conn.send_request_checked(&GrabKey {
-        owner_events: true,
-        grab_window: win,
-        modifiers,
-        key: *keycode,
-        pointer_mode: GrabMode::Async,
-        keyboard_mode: GrabMode::Async,
-    })).collect::<Vec<_>>();
-
-    grab_cookies.into_iter()
-        .try_for_each(|cookie|
-            x_conn.check_request(cookie).map_err(|e| GenericError(format!("Failed to grab hotkey: {:?}", e)).into()))
+impl From<Args> for Config {
+    fn from(args: Args) -> Config {
+        Config {
+            device: args.device,
+            audio_backend: args.audio_backend,
+            controls: args.device_controls,
+            method: args.method,
+            capture_volume: args.capture_volume,
+            muted_volume: args.muted_volume,
+            restore_volume: args.restore_volume,
+            playback_control: args.playback_control,
+            unmute_delay: args.unmute_delay,
+            remute_delay: args.remute_delay,
+            debounce: args.debounce,
+            push_modifiers: args.push_modifiers,
+            push_keycode: args.push_keycode,
+            push_keysym: args.push_keysym,
+            push_hotkey: args.push_hotkey,
+            unmute_key: args.unmute_key,
+            mute_key: args.mute_key,
+            panic_key: args.panic_key,
+            push_button: args.push_button,
+            toggle_modifiers: args.toggle_modifiers,
+            toggle_keycode: args.toggle_keycode,
+            toggle_keysym: args.toggle_keysym,
+            strict_modifiers: args.strict_modifiers || !args.ignore_lock_modifiers,
+            mode: args.mode,
+            tap_toggle_threshold: args.tap_toggle_threshold,
+            double_tap_lock: args.double_tap_lock,
+            min_hold: args.min_hold,
+            max_unmute_ms: args.max_unmute_ms,
+            auto_mute_idle: args.auto_mute_idle,
+            invert: args.invert,
+            beep: args.beep,
+            sound_on: args.sound_on,
+            sound_off: args.sound_off,
+            open_mic_reminder: args.open_mic_reminder,
+            reminder_sound: args.reminder_sound,
+            beep_device: args.beep_device,
+            no_repeat_filter: args.no_repeat_filter,
+            auto_mute_on_focus_loss: args.auto_mute_on_focus_loss,
+            no_stuck_key_check: args.no_stuck_key_check,
+            reconnect: args.reconnect && !args.no_reconnect,
+            x11_reconnect_attempts: args.x11_reconnect_attempts,
+            resume_state: args.resume_state,
+            alsa_retry_limit: args.alsa_retry_limit,
+            #[cfg(feature = "notifications")]
+            notify: args.notify,
+            #[cfg(feature = "led")]
+            led_on: args.led_on,
+            #[cfg(feature = "led")]
+            led_off: args.led_off,
+            #[cfg(feature = "led")]
+            led: args.led,
+            exit_state: args.exit_state,
+            dry_run: args.dry_run,
+            no_sd_notify: args.no_sd_notify,
+            pid_file: args.pid_file,
+            input_backend: args.input_backend,
+            display: args.display,
+            input_device: args.input_device,
+            passive: args.passive,
+            grab_retries: args.grab_retries,
+            grab_retry_interval: args.grab_retry_interval,
+            grab_fallback: args.grab_fallback,
+            output: args.output,
+            control_socket: args.control_socket,
+            #[cfg(feature = "dbus")]
+            dbus: args.dbus,
+            status_socket: args.status_socket,
+            signal_unmute_delay: args.signal_unmute_delay,
+            state_file: args.state_file,
+            state_file_format: args.state_file_format,
+            measure_latency: args.measure_latency,
+            profiles: args.profiles,
+        }
+    }
 }
 
-fn set_expected_capture_state(expected_capture_state: &Arc<AtomicBool>, mixer_capture_elem: &Selem, state: bool) {
-    expected_capture_state.store(state, Ordering::Release);
-    if let Err(e) = set_capture_state(mixer_capture_elem, state) {
-        println!("{} Error setting mixer capture state: {:?}", log_timestamp(), e);
-        panic!("Failure to set mixer caputre state");
+/// Logs to stdout, or to stderr when stdout is used for something else, at the level from $RUST_LOG if set
+fn init_logging(level: LogLevel, to_stderr: bool) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(level.into()).into())
+        .from_env_lossy();
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_timer(ChronoLocal::new(String::from("%Y-%m-%d %H:%M:%S%.3f")))
+        .with_target(false);
+    if to_stderr {
+        builder.with_writer(io::stderr).with_ansi(io::stderr().is_terminal()).init();
+    } else {
+        builder.with_ansi(io::stdout().is_terminal()).init();
     }
 }
 
-// -------------
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
 
-fn log_timestamp() -> String {
-    format!("{}", Local::now().format("%Y-%m-%d %H:%M:%S%.3f"))
-}
+    use super::*;
 
-// -------------
+    #[test]
+    fn completions_cover_the_options() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut Args::command(), "push-to-talk", &mut script);
+            assert!(String::from_utf8(script).unwrap().contains("push-keysym"), "{}", shell);
+        }
+    }
 
-#[derive(Debug)]
-struct GenericError<S: AsRef<str> + Display + Debug>(S);
+    #[test]
+    fn parses_several_push_keycodes() {
+        let args = Args::try_parse_from(["ptt", "-k", "62,50,108"]).unwrap();
+        assert_eq!(args.push_keycode, vec![62, 50, 108]);
+    }
 
-impl<S: AsRef<str> + Display + Debug> Error for GenericError<S> {}
+    #[test]
+    fn hotkey_key_is_the_push_keysym() {
+        let args = Args::try_parse_from(["ptt", "--hotkey-key", "Shift_L"]).unwrap();
+        assert_eq!(args.push_keysym.as_deref(), Some("Shift_L"));
+    }
 
-impl<S: AsRef<str> + Display + Debug> Display for GenericError<S> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "GenericError: {}", self.0)
+    #[test]
+    fn verbose_and_quiet_move_the_log_level() {
+        assert_eq!(LogLevel::Info.adjusted(0, 0), LogLevel::Info);
+        assert_eq!(LogLevel::Info.adjusted(1, 0), LogLevel::Debug);
+        assert_eq!(LogLevel::Info.adjusted(5, 0), LogLevel::Trace);
+        assert_eq!(LogLevel::Info.adjusted(0, 2), LogLevel::Error);
+        assert_eq!(LogLevel::Warn.adjusted(0, 5), LogLevel::Error);
+        assert_eq!(LogLevel::Info.adjusted(1, 1), LogLevel::Info);
     }
 }
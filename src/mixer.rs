@@ -0,0 +1,531 @@
+//! Keeping the capture state on the mixers, through an `AudioBackend` so the enforce loop does not depend on alsa
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alsa::Mixer;
+use alsa::mixer::{Selem, SelemChannelId, SelemId};
+use alsa::poll::Descriptors;
+use tracing::{debug, info, warn};
+
+use crate::audio::AudioBackend;
+use crate::capture_state::ExpectedCaptureState;
+use crate::error::PttError;
+use crate::{latency, Config, Method};
+
+#[cfg(feature = "pulse")]
+pub mod pulse;
+
+/// How long to wait before reopening a failed mixer, doubled for each failed attempt
+const MIXER_REOPEN_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MIXER_REOPEN_MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// An alsa mixer waited on for longer than this may have gone stale, so it is opened anew
+const ALSA_STALE_AFTER: Duration = Duration::from_millis(1000);
+
+/// A simple control of an alsa mixer, told apart from others of the same name by its index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlId {
+    pub name: String,
+    pub index: u32,
+}
+
+impl ControlId {
+    pub fn new(name: &str, index: u32) -> ControlId {
+        ControlId { name: name.to_string(), index }
+    }
+
+    fn selem_id(&self) -> SelemId {
+        SelemId::new(&self.name, self.index)
+    }
+}
+
+/// Like given to --control, `Capture` or `Capture,1`
+impl fmt::Display for ControlId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            0 => write!(f, "{}", self.name),
+            index => write!(f, "{},{}", self.name, index),
+        }
+    }
+}
+
+/// How the mixer control gets muted, remembering the volumes from before muting to restore them when unmuting
+#[derive(Clone)]
+pub struct Capture {
+    method: Method,
+    /// percentages of the capture volume range
+    unmuted_volume: Option<u8>,
+    muted_volume: u8,
+    /// --restore-volume, for the switch method
+    restore_volume: bool,
+    saved_volumes: RefCell<Vec<(SelemChannelId, i64)>>,
+}
+
+impl Capture {
+    pub fn new(method: Method, unmuted_volume: Option<u8>, muted_volume: u8, restore_volume: bool) -> Capture {
+        Capture { method, unmuted_volume, muted_volume, restore_volume, saved_volumes: RefCell::new(Vec::new()) }
+    }
+}
+
+/// The controls of an alsa mixer, muted together
+pub struct AlsaBackend {
+    mixer: Mixer,
+    device: String,
+    /// each with its own saved volumes
+    controls: Vec<(ControlId, Capture)>,
+    /// the --playback-control, switched off while the capture is unmuted
+    playback_control: Option<String>,
+}
+
+impl AlsaBackend {
+    /// Opens the mixer, failing early on a wrong device or control
+    pub fn open(device: &str, controls: &[ControlId], capture: Capture) -> Result<AlsaBackend, PttError> {
+        let mixer = open_mixer(device)?;
+        let controls = controls.iter()
+            .map(|control| {
+                let mut capture = capture.clone();
+                let volume_only = mixer.find_selem(&control.selem_id()).is_some_and(|selem| !selem.has_capture_switch() && selem.has_capture_volume());
+                if capture.method == Method::Switch && volume_only {
+                    info!("Mixer control {} of {} has no capture switch, muting it with its capture volume instead", control, device);
+                    capture.method = Method::Volume;
+                }
+                (control.clone(), capture)
+            })
+            .collect();
+        let backend = AlsaBackend { mixer, device: device.to_string(), controls, playback_control: None };
+        for (control, capture) in &backend.controls {
+            get_alsa_mixer_capture_elem(&backend.mixer, control, capture)?;
+        }
+        Ok(backend)
+    }
+
+    /// Also switches the playback of the control off while unmuted, failing early if it has no playback switch
+    pub fn with_playback_control(mut self, playback_control: Option<&str>) -> Result<AlsaBackend, PttError> {
+        if let Some(playback_control) = playback_control {
+            get_alsa_mixer_playback_elem(&self.mixer, playback_control)?;
+        }
+        self.playback_control = playback_control.map(str::to_string);
+        Ok(self)
+    }
+
+    /// Sets the playback switch of the --playback-control, if any, to the opposite of the capture state
+    pub fn set_playback_state(&self, capture_state: bool) -> Result<(), PttError> {
+        if let Some(playback_control) = &self.playback_control {
+            get_alsa_mixer_playback_elem(&self.mixer, playback_control)?.set_playback_switch_all((!capture_state).into())?;
+        }
+        Ok(())
+    }
+
+    fn capture_elems(&self) -> Result<Vec<CaptureElem<'_>>, PttError> {
+        get_alsa_mixer_capture_elems(&self.mixer, &self.controls)
+    }
+}
+
+impl AudioBackend for AlsaBackend {
+    fn get_capture(&self) -> Result<Option<bool>, PttError> {
+        let states = self.capture_elems()?.iter().map(get_unanimous_capture_state).collect::<Result<Vec<_>, _>>()?;
+        Ok(unanimous(&states))
+    }
+
+    fn set_capture(&mut self, state: bool) -> Result<(), PttError> {
+        // each control on its own, so that one failing does not keep the others from being set
+        let mut result = Ok(());
+        for (control, capture) in &self.controls {
+            let set = get_alsa_mixer_capture_elem(&self.mixer, control, capture)
+                .and_then(|mixer_capture_elem| Ok(set_capture_state(&mixer_capture_elem, state)?));
+            result = result.and(set);
+        }
+        result.and(self.set_playback_state(state))
+    }
+
+    fn wait_for_external_change(&mut self, wake_up: libc::pollfd) -> Result<(), PttError> {
+        let before = Instant::now();
+        let mut fds = self.mixer.get()?;
+        fds.push(wake_up);
+        alsa::poll::poll(&mut fds, -1)?;
+        self.mixer.handle_events()?;
+        if before.elapsed() > ALSA_STALE_AFTER {
+            self.reopen()?;
+        }
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), PttError> {
+        self.mixer = open_mixer(&self.device)?;
+        Ok(())
+    }
+}
+
+pub fn open_mixer(device: &str) -> Result<Mixer, PttError> {
+    Mixer::new(device, false).map_err(|source| PttError::AlsaOpen { device: device.to_string(), source })
+}
+
+pub fn enforce_mixer_capture_state(mut backend: Box<dyn AudioBackend + Send>, expected_capture_state: &ExpectedCaptureState, enforcer: usize, running: &AtomicBool, device: &str, config: &Config) -> Result<(), PttError> {
+    let mut backoff = None;
+    let mut failed_reopens = 0;
+    while running.load(Ordering::Acquire) {
+        let result = match backoff {
+            Some(_) => backend.reopen(),
+            None => Ok(()),
+        }.and_then(|_| enforce_with_backend(backend.as_mut(), expected_capture_state, enforcer, running, device, &mut backoff, config));
+        if let Err(e) = result {
+            // the backoff is only cleared once reopening has worked
+            failed_reopens = if backoff.is_some() { failed_reopens + 1 } else { 0 };
+            if !config.reconnect {
+                return Err(e);
+            }
+            if config.alsa_retry_limit.is_some_and(|limit| failed_reopens >= limit) {
+                return Err(PttError::AlsaRetryLimit { device: device.to_string(), attempts: failed_reopens, source: Box::new(e) });
+            }
+            let delay = backoff.map_or(MIXER_REOPEN_INITIAL_BACKOFF, |backoff: Duration| (backoff * 2).min(MIXER_REOPEN_MAX_BACKOFF));
+            warn!("Mixer of {} failed: {}, reopening in {:?}", device, e, delay);
+            backoff = Some(delay);
+            // in steps, so that exiting does not have to wait for the whole delay
+            let deadline = Instant::now() + delay;
+            while running.load(Ordering::Acquire) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets the expected capture state whenever woken up, and sets it back whenever something else changes it, until stopped or the mixer fails;
+/// with --dry-run only logs the changes it would make
+fn enforce_with_backend(backend: &mut dyn AudioBackend, expected_capture_state: &ExpectedCaptureState, enforcer: usize, running: &AtomicBool, device: &str, backoff: &mut Option<Duration>, config: &Config) -> Result<(), PttError> {
+    let mut reopen_generation = expected_capture_state.reopen_generation();
+    // to tell changes by the hotkeys apart from fixes
+    let mut applied = None;
+    loop {
+        // checked before reading the state, so that the final state is set when stopping
+        let stopping = !running.load(Ordering::Acquire);
+        let actual = backend.get_capture()?;
+        if backoff.take().is_some() {
+            info!("Reopened mixer of {}, restoring the capture state", device);
+        }
+        let expected = expected_capture_state.get();
+        if actual == Some(expected) {
+            latency::switch_confirmed(expected);
+        } else if config.dry_run {
+            if applied != Some(expected) {
+                info!("Dry run, not {} {}", if expected { "unmuting" } else { "muting" }, device);
+            }
+        } else {
+            if applied == Some(expected) {
+                debug!("Fixing capture state to {}", if expected { "unmuted" } else { "muted" });
+            }
+            match backend.set_capture(expected) {
+                Ok(()) => latency::switch_set(expected),
+                Err(e) => warn!("Error setting capture state: {:?}", e),
+            }
+        }
+        applied = Some(expected);
+        if stopping {
+            return Ok(());
+        }
+
+        backend.wait_for_external_change(expected_capture_state.poll_descriptor(enforcer))?;
+        expected_capture_state.clear_wake_ups(enforcer);
+        if expected_capture_state.reopen_generation() != reopen_generation {
+            reopen_generation = expected_capture_state.reopen_generation();
+            backend.reopen()?;
+        }
+    }
+}
+
+/// The raw capture volume for a percentage of the range the control supports
+fn capture_volume_from_percent(selem: &Selem, percent: u8) -> i64 {
+    let (min, max) = selem.get_capture_volume_range();
+    min + (max - min) * i64::from(percent) / 100
+}
+
+/// The mixer control together with how to mute it
+pub struct CaptureElem<'a> {
+    selem: Selem<'a>,
+    capture: &'a Capture,
+}
+
+pub fn get_alsa_mixer_capture_elem<'a>(alsa_mixer: &'a Mixer, control: &ControlId, capture: &'a Capture) -> Result<CaptureElem<'a>, PttError> {
+    let selem = alsa_mixer.find_selem(&control.selem_id()).ok_or_else(|| PttError::MixerControlNotFound { name: control.name.clone(), index: control.index })?;
+    match capture.method {
+        Method::Switch if !selem.has_capture_switch() => return Err(PttError::NoCaptureSwitch),
+        Method::Volume if !selem.has_capture_volume() => return Err(PttError::NoCaptureVolume),
+        _ => (),
+    }
+    Ok(CaptureElem { selem, capture })
+}
+
+fn get_alsa_mixer_capture_elems<'a>(alsa_mixer: &'a Mixer, controls: &'a [(ControlId, Capture)]) -> Result<Vec<CaptureElem<'a>>, PttError> {
+    controls.iter().map(|(control, capture)| get_alsa_mixer_capture_elem(alsa_mixer, control, capture)).collect()
+}
+
+fn get_alsa_mixer_playback_elem<'a>(alsa_mixer: &'a Mixer, control: &str) -> Result<Selem<'a>, PttError> {
+    let selem = alsa_mixer.find_selem(&SelemId::new(control, 0)).ok_or_else(|| PttError::MixerControlNotFound { name: control.to_string(), index: 0 })?;
+    if !selem.has_playback_switch() {
+        return Err(PttError::NoPlaybackSwitch(control.to_string()));
+    }
+    Ok(selem)
+}
+
+/// The channels the control has, since reading or setting the others fails on e.g. mono controls
+fn capture_channels<'a>(mixer_capture_elem: &'a CaptureElem<'_>) -> impl Iterator<Item = SelemChannelId> + 'a {
+    SelemChannelId::all().iter().copied().filter(|channel| mixer_capture_elem.selem.has_capture_channel(*channel))
+}
+
+/// The state of each channel the control has
+fn channel_states<E>(has_channel: impl Fn(SelemChannelId) -> bool, state: impl FnMut(SelemChannelId) -> Result<bool, E>) -> Result<Vec<bool>, E> {
+    SelemChannelId::all().iter().copied().filter(|channel| has_channel(*channel)).map(state).collect()
+}
+
+pub fn get_unanimous_capture_state(mixer_capture_elem: &CaptureElem<'_>) -> Result<Option<bool>, PttError> {
+    let selem = &mixer_capture_elem.selem;
+    let has_channel = |channel| selem.has_capture_channel(channel);
+    let states = match mixer_capture_elem.capture.method {
+        Method::Switch => {
+            let states = channel_states(has_channel, |channel| selem.get_capture_switch(channel).map(|value| value != 0))?;
+            // with --restore-volume, a volume that drifted while unmuted counts as not quite unmuted, so that the enforce loop restores it
+            let saved_volumes = mixer_capture_elem.capture.saved_volumes.borrow();
+            if mixer_capture_elem.capture.restore_volume && states.iter().all(|state| *state)
+                && volume_drifted(&saved_volumes, || get_capture_volumes(mixer_capture_elem))? {
+                return Ok(None);
+            }
+            states
+        }
+        Method::Volume => {
+            // anything above the muted volume counts as unmuted
+            let muted = capture_volume_from_percent(selem, mixer_capture_elem.capture.muted_volume);
+            channel_states(has_channel, |channel| selem.get_capture_volume(channel).map(|volume| volume > muted))?
+        }
+    };
+    let first_channel_state = *states.first().ok_or(PttError::NoCaptureChannels)?;
+    if states.iter().all(|state| *state == first_channel_state) {
+        Ok(Some(first_channel_state))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The state all controls agree on, so that the enforce loop fixes them if any one of them is off
+fn unanimous(states: &[Option<bool>]) -> Option<bool> {
+    let first = *states.first()?;
+    states.iter().all(|state| *state == first).then_some(first).flatten()
+}
+
+pub fn set_capture_state(mixer_capture_elem: &CaptureElem<'_>, state: bool) -> Result<(), alsa::Error> {
+    let selem = &mixer_capture_elem.selem;
+    let capture = mixer_capture_elem.capture;
+    let muted = capture_volume_from_percent(selem, capture.muted_volume);
+    match capture.method {
+        Method::Switch => {
+            let restore_volume = capture.restore_volume && selem.has_capture_volume();
+            // taken before the switch changes, as that is what resets the volume on some drivers; not when already muted, to keep the volume from before
+            if restore_volume && !state && capture_channels(mixer_capture_elem).any(|channel| selem.get_capture_switch(channel).is_ok_and(|value| value != 0)) {
+                *capture.saved_volumes.borrow_mut() = get_capture_volumes(mixer_capture_elem)?;
+            }
+            for channel in capture_channels(mixer_capture_elem) {
+                selem.set_capture_switch(channel, state.into())?;
+            }
+            // with --capture-volume, the volume goes along with the switch, if the control has one
+            if let Some(percent) = capture.unmuted_volume.filter(|_| selem.has_capture_volume()) {
+                selem.set_capture_volume_all(if state { capture_volume_from_percent(selem, percent) } else { muted })?;
+            }
+            if restore_volume && state {
+                set_capture_volumes(mixer_capture_elem, &mut capture.saved_volumes.borrow_mut())?;
+            }
+        }
+        Method::Volume => {
+            let mut saved_volumes = capture.saved_volumes.borrow_mut();
+            if state {
+                match capture.unmuted_volume {
+                    Some(percent) => selem.set_capture_volume_all(capture_volume_from_percent(selem, percent))?,
+                    // started out muted, so there is nothing to restore; guessing a volume could be far too loud
+                    None if saved_volumes.is_empty() => warn!("Mixer control {} was muted before starting, so there is no capture volume to restore; turn it up by hand once, or set --capture-volume",
+                                                              selem.get_id().get_name().unwrap_or_default()),
+                    None => set_capture_volumes(mixer_capture_elem, &mut saved_volumes)?,
+                }
+            } else {
+                let volumes = get_capture_volumes(mixer_capture_elem)?;
+                // keep the volumes from before muting if already muted
+                if volumes.iter().any(|(_, volume)| *volume > muted) {
+                    *saved_volumes = volumes;
+                }
+                selem.set_capture_volume_all(muted)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The capture volume of each channel the control has
+fn get_capture_volumes(mixer_capture_elem: &CaptureElem<'_>) -> Result<Vec<(SelemChannelId, i64)>, alsa::Error> {
+    capture_channels(mixer_capture_elem)
+        .map(|channel| mixer_capture_elem.selem.get_capture_volume(channel).map(|volume| (channel, volume)))
+        .collect()
+}
+
+/// Sets the saved capture volume of each channel, warning if the driver did not keep them
+fn set_capture_volumes(mixer_capture_elem: &CaptureElem<'_>, volumes: &mut Vec<(SelemChannelId, i64)>) -> Result<(), alsa::Error> {
+    let selem = &mixer_capture_elem.selem;
+    if !restore_volumes(volumes, |channel, volume| selem.set_capture_volume(channel, volume), || get_capture_volumes(mixer_capture_elem))? {
+        warn!("Mixer control {} did not keep the restored capture volume", selem.get_id().get_name().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Whether the volumes differ from the saved ones, read only if any were saved
+fn volume_drifted<E>(saved: &[(SelemChannelId, i64)], volumes: impl FnOnce() -> Result<Vec<(SelemChannelId, i64)>, E>) -> Result<bool, E> {
+    Ok(!saved.is_empty() && volumes()? != saved)
+}
+
+/// Sets the saved volumes and reads them back, returning whether they were kept; what was kept is saved instead, so that the
+/// enforce loop does not keep fighting the driver. Nothing saved yet, like when starting out muted, is nothing to restore.
+fn restore_volumes<E>(saved: &mut Vec<(SelemChannelId, i64)>, mut set: impl FnMut(SelemChannelId, i64) -> Result<(), E>, volumes: impl FnOnce() -> Result<Vec<(SelemChannelId, i64)>, E>) -> Result<bool, E> {
+    if saved.is_empty() {
+        return Ok(true);
+    }
+    for (channel, volume) in saved.iter() {
+        set(*channel, *volume)?;
+    }
+    let kept = volumes()?;
+    if kept == *saved {
+        return Ok(true);
+    }
+    *saved = kept;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A mixer that changes as scripted whenever waited on, and stops the enforce loop once the script is over
+    struct MockBackend {
+        state: Cell<Option<bool>>,
+        writes: Arc<Mutex<Vec<bool>>>,
+        changes: VecDeque<Option<bool>>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl MockBackend {
+        fn new(state: Option<bool>, changes: &[Option<bool>], running: &Arc<AtomicBool>) -> (MockBackend, Arc<Mutex<Vec<bool>>>) {
+            let writes = Arc::new(Mutex::new(Vec::new()));
+            let backend = MockBackend { state: Cell::new(state), writes: writes.clone(), changes: changes.iter().copied().collect(), running: running.clone() };
+            (backend, writes)
+        }
+    }
+
+    impl AudioBackend for MockBackend {
+        fn get_capture(&self) -> Result<Option<bool>, PttError> {
+            Ok(self.state.get())
+        }
+
+        fn set_capture(&mut self, state: bool) -> Result<(), PttError> {
+            self.state.set(Some(state));
+            self.writes.lock().unwrap().push(state);
+            Ok(())
+        }
+
+        fn wait_for_external_change(&mut self, _wake_up: libc::pollfd) -> Result<(), PttError> {
+            match self.changes.pop_front() {
+                Some(state) => self.state.set(state),
+                None => self.running.store(false, Ordering::Release),
+            }
+            Ok(())
+        }
+
+        fn reopen(&mut self) -> Result<(), PttError> {
+            Ok(())
+        }
+    }
+
+    fn enforce(state: Option<bool>, changes: &[Option<bool>], expected: bool, dry_run: bool) -> Vec<bool> {
+        let running = Arc::new(AtomicBool::new(true));
+        let expected_capture_state = ExpectedCaptureState::new(expected, 1).unwrap();
+        let (backend, writes) = MockBackend::new(state, changes, &running);
+        let config = Config { reconnect: false, dry_run, ..Config::default() };
+        enforce_mixer_capture_state(Box::new(backend), &expected_capture_state, 0, &running, "mock", &config).unwrap();
+        Arc::into_inner(writes).unwrap().into_inner().unwrap()
+    }
+
+    #[test]
+    fn sets_expected_state_once() {
+        assert_eq!(enforce(Some(true), &[], false, false), [false]);
+    }
+
+    #[test]
+    fn leaves_matching_state_alone() {
+        assert_eq!(enforce(Some(false), &[], false, false), Vec::<bool>::new());
+    }
+
+    #[test]
+    fn controls_agree_only_if_all_do() {
+        assert_eq!(unanimous(&[Some(true), Some(true)]), Some(true));
+        assert_eq!(unanimous(&[Some(false)]), Some(false));
+        assert_eq!(unanimous(&[Some(false), Some(true)]), None);
+        assert_eq!(unanimous(&[Some(false), None]), None);
+    }
+
+    #[test]
+    fn reads_only_the_channels_of_a_mono_control() {
+        // like alsa, fails for the channels the control does not have
+        let mono = |channel| channel == SelemChannelId::mono();
+        let states = channel_states(mono, |channel| if mono(channel) { Ok(true) } else { Err(channel) });
+        assert_eq!(states, Ok(vec![true]));
+        let stereo = |channel| channel == SelemChannelId::FrontLeft || channel == SelemChannelId::FrontRight;
+        assert_eq!(channel_states(stereo, |channel| Ok::<_, ()>(channel == SelemChannelId::FrontLeft)), Ok(vec![true, false]));
+    }
+
+    #[test]
+    fn nothing_saved_is_not_restored() {
+        let mut saved = Vec::new();
+        let mut writes = Vec::new();
+        let kept = restore_volumes(&mut saved, |channel, volume| {
+            writes.push((channel, volume));
+            Ok::<_, ()>(())
+        }, || Ok(vec![(SelemChannelId::FrontLeft, 10)]));
+        assert_eq!(kept, Ok(true));
+        assert!(writes.is_empty() && saved.is_empty());
+        // so the volume of the first unmute is no drift to fix either
+        assert_eq!(volume_drifted(&saved, || Ok::<_, ()>(vec![(SelemChannelId::FrontLeft, 10)])), Ok(false));
+    }
+
+    #[test]
+    fn restores_the_saved_volumes_and_keeps_what_the_driver_kept() {
+        let mut saved = vec![(SelemChannelId::FrontLeft, 40), (SelemChannelId::FrontRight, 50)];
+        let mut writes = Vec::new();
+        let kept = restore_volumes(&mut saved, |channel, volume| {
+            writes.push((channel, volume));
+            Ok::<_, ()>(())
+        }, || Ok(vec![(SelemChannelId::FrontLeft, 40), (SelemChannelId::FrontRight, 48)]));
+        assert_eq!(kept, Ok(false));
+        assert_eq!(writes, [(SelemChannelId::FrontLeft, 40), (SelemChannelId::FrontRight, 50)]);
+        assert_eq!(saved, [(SelemChannelId::FrontLeft, 40), (SelemChannelId::FrontRight, 48)]);
+    }
+
+    #[test]
+    fn volume_changed_while_unmuted_drifted() {
+        let saved = [(SelemChannelId::FrontLeft, 40)];
+        assert_eq!(volume_drifted(&saved, || Ok::<_, ()>(vec![(SelemChannelId::FrontLeft, 40)])), Ok(false));
+        assert_eq!(volume_drifted(&saved, || Ok::<_, ()>(vec![(SelemChannelId::FrontLeft, 0)])), Ok(true));
+    }
+
+    #[test]
+    fn sets_back_changes_by_others() {
+        assert_eq!(enforce(Some(false), &[Some(true), None], false, false), [false, false]);
+    }
+
+    #[test]
+    fn dry_run_leaves_the_mixer_alone() {
+        assert_eq!(enforce(Some(true), &[Some(false)], false, true), Vec::<bool>::new());
+    }
+}
@@ -0,0 +1,124 @@
+//! Muting a PulseAudio source with pactl, which also works with the PulseAudio server of PipeWire
+//!
+//! Changes by other programs are noticed through `pactl subscribe`, so there is no polling either way. Running pactl instead of
+//! linking libpulse keeps the PulseAudio development files out of the build.
+
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use crate::error::PttError;
+use crate::audio::AudioBackend;
+
+/// The source muted by `--device default`
+const DEFAULT_SOURCE: &str = "@DEFAULT_SOURCE@";
+
+pub struct PulseBackend {
+    source: String,
+    subscription: Subscription,
+}
+
+/// A running `pactl subscribe`, stopped when dropped
+struct Subscription {
+    child: Child,
+    events: ChildStdout,
+    // the start of a line not read completely yet
+    partial_line: Vec<u8>,
+}
+
+impl PulseBackend {
+    /// Subscribes to the changes first, so none are missed after reading the state
+    pub fn open(device: &str) -> Result<PulseBackend, PttError> {
+        let source = if device == "default" { DEFAULT_SOURCE } else { device };
+        let backend = PulseBackend { source: source.to_string(), subscription: Subscription::start()? };
+        // fails early on a wrong source name
+        backend.get_capture()?;
+        Ok(backend)
+    }
+}
+
+impl AudioBackend for PulseBackend {
+    fn get_capture(&self) -> Result<Option<bool>, PttError> {
+        let output = pactl(&["get-source-mute", &self.source])?;
+        match output.trim() {
+            "Mute: yes" => Ok(Some(false)),
+            "Mute: no" => Ok(Some(true)),
+            output => Err(PttError::PactlFailed { command: String::from("get-source-mute"), message: format!("unexpected output {:?}", output) }),
+        }
+    }
+
+    fn set_capture(&mut self, state: bool) -> Result<(), PttError> {
+        pactl(&["set-source-mute", &self.source, if state { "0" } else { "1" }])?;
+        Ok(())
+    }
+
+    fn wait_for_external_change(&mut self, wake_up: libc::pollfd) -> Result<(), PttError> {
+        loop {
+            let mut fds = [libc::pollfd { fd: self.subscription.events.as_raw_fd(), events: libc::POLLIN, revents: 0 }, wake_up];
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e.into());
+            }
+            if fds[1].revents != 0 || self.subscription.read_source_events()? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn reopen(&mut self) -> Result<(), PttError> {
+        self.subscription = Subscription::start()?;
+        Ok(())
+    }
+}
+
+impl Subscription {
+    fn start() -> Result<Subscription, PttError> {
+        let mut child = Command::new("pactl")
+            .arg("subscribe")
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(PttError::Pactl)?;
+        let events = child.stdout.take().expect("stdout of pactl is piped");
+        Ok(Subscription { child, events, partial_line: Vec::new() })
+    }
+
+    /// Reads the events available, returning whether any of them may have changed the source, like a change of the default source
+    fn read_source_events(&mut self) -> Result<bool, PttError> {
+        let mut buf = [0; 4096];
+        let read = self.events.read(&mut buf)?;
+        if read == 0 {
+            return Err(PttError::PactlFailed { command: String::from("subscribe"), message: String::from("exited") });
+        }
+        self.partial_line.extend_from_slice(&buf[..read]);
+        let mut source_changed = false;
+        while let Some(end) = self.partial_line.iter().position(|byte| *byte == b'\n') {
+            let line = self.partial_line.drain(..=end).collect::<Vec<_>>();
+            // lines like "Event 'change' on source #52", while the many events of the playback streams are of no interest
+            let line = String::from_utf8_lossy(&line);
+            source_changed |= line.contains(" on source ") || line.contains(" on server ");
+        }
+        Ok(source_changed)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs pactl with English output, returning its output
+fn pactl(args: &[&str]) -> Result<String, PttError> {
+    let output = Command::new("pactl").args(args).env("LC_ALL", "C").stdin(Stdio::null()).output().map_err(PttError::Pactl)?;
+    if !output.status.success() {
+        return Err(PttError::PactlFailed { command: args[0].to_string(), message: String::from_utf8_lossy(&output.stderr).trim().to_string() });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
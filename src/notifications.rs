@@ -0,0 +1,65 @@
+//! Desktop notifications about the capture state, shown from a separate thread so a slow notification daemon does not delay the hotkeys
+
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use notify_rust::Notification;
+
+use tracing::warn;
+
+/// Changes within this time after the first one are shown as a single notification with the latest state
+const THROTTLE: Duration = Duration::from_millis(300);
+
+/// Short, since the next change replaces it anyway
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+static SENDER: Mutex<Option<Sender<bool>>> = Mutex::new(None);
+
+/// Starts showing notifications for the states passed to `capture_state_changed`
+pub fn start() {
+    let (sender, receiver) = mpsc::channel::<bool>();
+    thread::spawn(move || {
+        let mut shown_id = None;
+        let mut shown_state = None;
+        while let Ok(mut state) = receiver.recv() {
+            thread::sleep(THROTTLE);
+            while let Ok(later_state) = receiver.try_recv() {
+                state = later_state;
+            }
+            if shown_state == Some(state) {
+                continue;
+            }
+            let mut notification = Notification::new();
+            notification
+                .appname("push-to-talk")
+                .summary(if state { "Microphone ON" } else { "Microphone OFF" })
+                .icon(if state { "audio-input-microphone" } else { "audio-input-microphone-muted" })
+                .timeout(TIMEOUT);
+            // replace the previous notification instead of stacking them
+            if let Some(id) = shown_id {
+                notification.id(id);
+            }
+            match notification.show() {
+                Ok(handle) => {
+                    shown_id = Some(handle.id());
+                    shown_state = Some(state);
+                }
+                Err(e) => warn!("Failed to show notification: {}", e),
+            }
+        }
+    });
+    *SENDER.lock().unwrap() = Some(sender);
+}
+
+/// Lets the thread end once it is done with what was sent so far
+pub fn stop() {
+    SENDER.lock().unwrap().take();
+}
+
+pub fn capture_state_changed(state: bool) {
+    if let Some(sender) = SENDER.lock().unwrap().as_ref() {
+        let _ = sender.send(state);
+    }
+}
@@ -0,0 +1,93 @@
+//! A capture state change waiting for its delay, like `--unmute-delay`, without blocking the event loop meanwhile
+
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct PendingChange {
+    change: Option<(bool, Instant)>,
+}
+
+impl PendingChange {
+    /// The state that will be set once the delay has passed
+    pub fn state(&self) -> Option<bool> {
+        self.change.map(|(state, _)| state)
+    }
+
+    /// Replaces the change pending so far
+    pub fn schedule(&mut self, state: bool, delay: Duration) {
+        self.change = Some((state, Instant::now() + delay));
+    }
+
+    /// Returns the state that was pending
+    pub fn cancel(&mut self) -> Option<bool> {
+        self.change.take().map(|(state, _)| state)
+    }
+
+    /// Returns the state to set, once its delay has passed
+    pub fn take_due(&mut self, now: Instant) -> Option<bool> {
+        match self.change {
+            Some((state, due)) if now >= due => {
+                self.change = None;
+                Some(state)
+            }
+            _ => None,
+        }
+    }
+
+    /// How long to wait at most for the next event
+    pub fn time_left(&self, now: Instant) -> Option<Duration> {
+        self.change.map(|(_, due)| due.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture_state::ExpectedCaptureState;
+    use crate::hotkey_state::{apply_due_change, mute, unmute};
+
+    use super::*;
+
+    const UNMUTE_DELAY_MS: u64 = 150;
+
+    #[test]
+    fn due_after_delay() {
+        let mut pending = PendingChange::default();
+        pending.schedule(true, Duration::from_millis(UNMUTE_DELAY_MS));
+        let now = Instant::now();
+        assert_eq!(pending.take_due(now), None);
+        assert!(pending.time_left(now).is_some_and(|left| left <= Duration::from_millis(UNMUTE_DELAY_MS)));
+        assert_eq!(pending.take_due(now + Duration::from_secs(1)), Some(true));
+        assert_eq!(pending.state(), None);
+    }
+
+    #[test]
+    fn press_then_unmuted_after_delay() {
+        let expected = ExpectedCaptureState::new(false, 1).unwrap();
+        let mut pending = PendingChange::default();
+        unmute(&expected, &mut pending, UNMUTE_DELAY_MS);
+        assert!(!expected.get());
+        apply_due_change(&expected, &mut pending, Instant::now() + Duration::from_secs(1));
+        assert!(expected.get());
+    }
+
+    #[test]
+    fn release_within_unmute_delay_never_unmutes() {
+        let expected = ExpectedCaptureState::new(false, 1).unwrap();
+        let mut pending = PendingChange::default();
+        unmute(&expected, &mut pending, UNMUTE_DELAY_MS);
+        mute(&expected, &mut pending);
+        assert!(!expected.get());
+        apply_due_change(&expected, &mut pending, Instant::now() + Duration::from_secs(1));
+        assert!(!expected.get());
+    }
+
+    #[test]
+    fn press_within_remute_delay_stays_unmuted() {
+        let expected = ExpectedCaptureState::new(true, 1).unwrap();
+        let mut pending = PendingChange::default();
+        pending.schedule(false, Duration::from_millis(UNMUTE_DELAY_MS));
+        unmute(&expected, &mut pending, UNMUTE_DELAY_MS);
+        apply_due_change(&expected, &mut pending, Instant::now() + Duration::from_secs(1));
+        assert!(expected.get());
+    }
+}
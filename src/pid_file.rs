@@ -0,0 +1,35 @@
+//! The `--pid-file`, removed again when dropped
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the PID of this process to the file, unless it names another running process
+    pub fn create(path: &Path) -> Result<PidFile, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                if let Ok(pid) = text.trim().parse::<u32>() {
+                    if pid != process::id() && Path::new("/proc").join(pid.to_string()).exists() {
+                        return Err(format!("Already running as PID {} according to {}, kill it first with `kill {}`", pid, path.display(), pid));
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(format!("Failed to read PID file {}: {}", path.display(), e)),
+        }
+        fs::write(path, format!("{}\n", process::id())).map_err(|e| format!("Failed to write PID file {}: {}", path.display(), e))?;
+        Ok(PidFile { path: path.to_path_buf() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,119 @@
+//! Capture controls per application from the `[profiles]` of the config file, picked by the class of the active window
+//!
+//! The X event loop hands the class of each newly active window over with `window_class_changed`, and wakes up the
+//! enforce threads. The enforce thread of the first device owns the `ProfileManager`, which then switches to the
+//! control of the first profile whose pattern matches the class, or back to the first --device and --control.
+
+use std::sync::Mutex;
+
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::capture_state::ExpectedCaptureState;
+use crate::error::PttError;
+use crate::audio::AudioBackend;
+use crate::mixer::{AlsaBackend, Capture, ControlId};
+
+/// A `[profiles]` entry of the config file
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// matched against the class of the active window, as the second string of `xprop WM_CLASS`
+    pub pattern: Regex,
+    pub device: String,
+    pub control: ControlId,
+}
+
+/// The class of the window that became active, until the enforce thread picks it up
+static WINDOW_CLASS: Mutex<Option<String>> = Mutex::new(None);
+
+/// Called from the X event loop whenever another window becomes active
+pub fn window_class_changed(class: String, expected_capture_state: &ExpectedCaptureState) {
+    *WINDOW_CLASS.lock().unwrap() = Some(class);
+    expected_capture_state.wake();
+}
+
+pub struct ProfileManager {
+    /// the first --device and --control, used while no pattern matches
+    default: AlsaBackend,
+    profiles: Vec<(Profile, AlsaBackend)>,
+    /// index into `profiles`, or `None` for the default
+    active: Option<usize>,
+    /// --dry-run, the control left behind is not muted either
+    dry_run: bool,
+}
+
+impl ProfileManager {
+    /// Opens the controls of all profiles, failing early on a wrong device or control
+    pub fn open(default: AlsaBackend, profiles: &[Profile], capture: &Capture, dry_run: bool) -> Result<ProfileManager, PttError> {
+        let profiles = profiles.iter()
+            .map(|profile| Ok((profile.clone(), AlsaBackend::open(&profile.device, std::slice::from_ref(&profile.control), capture.clone())?)))
+            .collect::<Result<_, PttError>>()?;
+        Ok(ProfileManager { default, profiles, active: None, dry_run })
+    }
+
+    /// Switches to the control of the first profile matching the window class, or to the default one, leaving the previous control muted
+    pub fn select_by_window_class(&mut self, class: &str) -> Option<&Profile> {
+        let selected = self.profiles.iter().position(|(profile, _)| profile.pattern.is_match(class));
+        if selected != self.active {
+            // the state is set on the new control by the enforce loop right after
+            if self.dry_run {
+                info!("Dry run, not muting the control left behind");
+            } else if let Err(e) = self.active_backend_mut().set_capture(false) {
+                warn!("Failed to mute the control left behind: {}", e);
+            }
+            self.active = selected;
+            match self.active_profile() {
+                Some(profile) => info!("Window class {} matches profile {}, using mixer control {} of {}", class, profile.pattern, profile.control, profile.device),
+                None => info!("Window class {} matches no profile, using the default mixer control", class),
+            }
+            // events of an inactive mixer are not handled, so its state may be stale
+            if let Err(e) = self.active_backend_mut().reopen() {
+                warn!("Failed to reopen the mixer of the profile: {}", e);
+            }
+        }
+        self.active_profile()
+    }
+
+    fn active_profile(&self) -> Option<&Profile> {
+        self.active.map(|active| &self.profiles[active].0)
+    }
+
+    fn active_backend(&self) -> &AlsaBackend {
+        self.active.map_or(&self.default, |active| &self.profiles[active].1)
+    }
+
+    fn active_backend_mut(&mut self) -> &mut AlsaBackend {
+        match self.active {
+            Some(active) => &mut self.profiles[active].1,
+            None => &mut self.default,
+        }
+    }
+}
+
+impl AudioBackend for ProfileManager {
+    fn get_capture(&self) -> Result<Option<bool>, PttError> {
+        self.active_backend().get_capture()
+    }
+
+    fn set_capture(&mut self, state: bool) -> Result<(), PttError> {
+        self.active_backend_mut().set_capture(state)?;
+        // the --playback-control stays with the default control
+        if self.active.is_some() {
+            self.default.set_playback_state(state)?;
+        }
+        Ok(())
+    }
+
+    fn wait_for_external_change(&mut self, wake_up: libc::pollfd) -> Result<(), PttError> {
+        self.active_backend_mut().wait_for_external_change(wake_up)?;
+        let class = WINDOW_CLASS.lock().unwrap().take();
+        if let Some(class) = class {
+            self.select_by_window_class(&class);
+        }
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> Result<(), PttError> {
+        self.active_backend_mut().reopen()
+    }
+}
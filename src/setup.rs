@@ -0,0 +1,169 @@
+//! The `setup` subcommand, asking for the device, the mixer control, the hotkey and the unmute delay and writing them to the config file
+//!
+//! With --noninteractive nothing is asked: the default device and the automatically detected control are taken, the
+//! default hotkey and unmute delay are kept, and anything that would need a choice fails instead.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use alsa::Ctl;
+use alsa::mixer::Selem;
+use dialoguer::{Confirm, Input, Select};
+use xcb::x::{self, Event, GrabMode, Keycode, ModMask};
+
+use push_to_talk::error::PttError;
+use push_to_talk::mixer::{open_mixer, ControlId};
+use push_to_talk::{auto_detect_capture_control, format_modifiers, open_x, Method};
+
+use crate::config;
+
+/// What the wizard found out, in the order of the keys of the config file
+struct Setup {
+    device: String,
+    control: ControlId,
+    method: Method,
+    hotkey: Option<(Keycode, ModMask)>,
+    unmute_delay: Option<u64>,
+}
+
+pub fn run(config_path: Option<&Path>, display: Option<&str>, noninteractive: bool) -> Result<(), PttError> {
+    let path = config_path.map(Path::to_path_buf)
+        .or_else(config::default_config_path)
+        .ok_or_else(|| PttError::Setup("no place for the config file, HOME is not set, give --config".into()))?;
+    if path.exists() {
+        if noninteractive {
+            return Err(PttError::Setup(format!("config file {} already exists, run setup without --noninteractive to overwrite it", path.display())));
+        }
+        if !Confirm::new().with_prompt(format!("Overwrite the config file {}?", path.display())).default(false).interact()? {
+            return Ok(());
+        }
+    }
+
+    let setup = if noninteractive {
+        let control = auto_detect_capture_control("default", Method::Switch)?;
+        Setup { device: "default".into(), control, method: Method::Switch, hotkey: None, unmute_delay: None }
+    } else {
+        ask(display)?
+    };
+    write_config(&path, &setup)?;
+    println!("Wrote {}, start push-to-talk to use it", path.display());
+    Ok(())
+}
+
+fn ask(display: Option<&str>) -> Result<Setup, PttError> {
+    let devices = list_devices()?;
+    let names = devices.iter().map(|(device, name)| format!("{} ({})", device, name)).collect::<Vec<_>>();
+    let device = devices.into_iter()
+        .nth(Select::new().with_prompt("Sound card of the microphone").items(&names).default(0).interact()?)
+        .map(|(device, _)| device)
+        .unwrap_or_default();
+
+    let controls = list_capture_controls(&device)?;
+    if controls.is_empty() {
+        return Err(PttError::NoCaptureControl { device, method: "switch or volume" });
+    }
+    // the same choice as --control auto, if it has one
+    let detected = auto_detect_capture_control(&device, Method::Switch).ok();
+    let names = controls.iter()
+        .map(|(control, method)| format!("{} (capture {})", control, if *method == Method::Switch { "switch" } else { "volume only" }))
+        .collect::<Vec<_>>();
+    let selected = Select::new()
+        .with_prompt("Mixer control of the microphone")
+        .items(&names)
+        .default(controls.iter().position(|(control, _)| Some(control) == detected.as_ref()).unwrap_or_default())
+        .interact()?;
+    let (control, method) = controls[selected].clone();
+
+    println!("Press the push-to-talk hotkey, together with any modifiers to hold down with it");
+    let hotkey = capture_hotkey(display)?;
+    let modifiers = format_modifiers(hotkey.1);
+    println!("Got keycode {} with modifiers {}", hotkey.0, if modifiers.is_empty() { "none" } else { &modifiers });
+
+    let unmute_delay = Input::new()
+        .with_prompt("Milliseconds to wait before unmuting, so the click of the hotkey is not heard")
+        .default(150)
+        .interact_text()?;
+
+    Ok(Setup { device, control, method, hotkey: Some(hotkey), unmute_delay: Some(unmute_delay) })
+}
+
+/// The default device followed by each sound card, with a description to show
+fn list_devices() -> Result<Vec<(String, String)>, PttError> {
+    let mut devices = vec![("default".to_string(), "the default device of the alsa configuration".to_string())];
+    for card in alsa::card::Iter::new() {
+        let card_info = Ctl::from_card(&card?, false)?.card_info()?;
+        devices.push((format!("hw:{}", card_info.get_id()?), card_info.get_longname()?.to_string()));
+    }
+    Ok(devices)
+}
+
+/// The controls that can be muted, with the method to do so
+fn list_capture_controls(device: &str) -> Result<Vec<(ControlId, Method)>, PttError> {
+    let alsa_mixer = open_mixer(device)?;
+    let controls = alsa_mixer.iter()
+        .filter_map(Selem::new)
+        .filter(|selem| selem.has_capture_switch() || selem.has_capture_volume())
+        .map(|selem| Ok((ControlId::new(selem.get_id().get_name()?, selem.get_id().get_index()), if selem.has_capture_switch() { Method::Switch } else { Method::Volume })))
+        .collect::<Result<_, alsa::Error>>()?;
+    Ok(controls)
+}
+
+/// Grabs the keyboard until a key is released, returning that key with the modifiers held down when it was pressed
+fn capture_hotkey(display: Option<&str>) -> Result<(Keycode, ModMask), PttError> {
+    let (x_conn, roots) = open_x(display)?;
+    let cookie = x_conn.send_request(&x::GrabKeyboard {
+        owner_events: false,
+        grab_window: roots[0],
+        time: x::CURRENT_TIME,
+        pointer_mode: GrabMode::Async,
+        keyboard_mode: GrabMode::Async,
+    });
+    let reply = x_conn.wait_for_reply(cookie).map_err(|e| PttError::X11Request("grab the keyboard", e))?;
+    if reply.status() != x::GrabStatus::Success {
+        return Err(PttError::HotkeyGrab(format!("Failed to grab the keyboard: {:?}", reply.status())));
+    }
+
+    // like for the hotkeys, the state of a press does not yet include the own modifier of a modifier key, so a lone Shift_L works too;
+    // the release of the key that answered the previous prompt is skipped, as its press came before the grab
+    let mut pressed = HashMap::new();
+    let result = loop {
+        match x_conn.wait_for_event() {
+            Ok(xcb::Event::X(Event::KeyPress(evt))) => {
+                // the state of autorepeated presses includes the own modifier
+                pressed.entry(evt.detail()).or_insert(evt.state());
+            }
+            Ok(xcb::Event::X(Event::KeyRelease(evt))) => {
+                if let Some(state) = pressed.get(&evt.detail()) {
+                    // the lock modifiers are states rather than keys held down
+                    break Ok((evt.detail(), ModMask::from_bits_truncate(state.bits()) & !(ModMask::LOCK | ModMask::N2)));
+                }
+            }
+            Ok(_) => (),
+            Err(e) => break Err(e.into()),
+        }
+    };
+    x_conn.send_request(&x::UngrabKeyboard { time: x::CURRENT_TIME });
+    x_conn.flush()?;
+    result
+}
+
+fn write_config(path: &Path, setup: &Setup) -> Result<(), PttError> {
+    let mut table = toml::Table::new();
+    table.insert("device".into(), setup.device.clone().into());
+    table.insert("control".into(), setup.control.to_string().into());
+    table.insert("method".into(), if setup.method == Method::Switch { "switch" } else { "volume" }.into());
+    if let Some((keycode, modifiers)) = setup.hotkey {
+        table.insert("push_keycode".into(), toml::Value::Array(vec![i64::from(keycode).into()]));
+        table.insert("push_modifiers".into(), format_modifiers(modifiers).into());
+    }
+    if let Some(unmute_delay) = setup.unmute_delay {
+        table.insert("unmute_delay".into(), toml::Value::Integer(unmute_delay as i64));
+    }
+    let text = format!("# Written by push-to-talk setup, see config.example.toml for all keys\n{}", table);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
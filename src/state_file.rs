@@ -0,0 +1,80 @@
+//! The `--state-file`, holding the current capture state for clients that just read a file every now and then
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::PttError;
+
+static STATE_FILE: Mutex<Option<StateFile>> = Mutex::new(None);
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StateFileFormat {
+    /// muted or unmuted on a line
+    Text,
+    /// like {"muted":true,"ts":1710000000}
+    Json,
+}
+
+#[derive(Debug)]
+struct StateFile {
+    path: PathBuf,
+    format: StateFileFormat,
+}
+
+#[derive(Serialize)]
+struct StateFileContent {
+    muted: bool,
+    /// Unix time in seconds
+    ts: u64,
+}
+
+impl StateFile {
+    /// Renames the new content into place, so a reader never sees a partly written file
+    fn write(&self, state: bool) -> io::Result<()> {
+        let content = match self.format {
+            StateFileFormat::Text => format!("{}\n", if state { "unmuted" } else { "muted" }),
+            StateFileFormat::Json => {
+                let content = StateFileContent {
+                    muted: !state,
+                    ts: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default(),
+                };
+                serde_json::to_string(&content).expect("Failed to serialize state") + "\n"
+            }
+        };
+        let mut tmp_path = OsString::from(&self.path);
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Writes the initial state, and from then on the states passed to `capture_state_changed`
+pub fn start(path: &Path, format: StateFileFormat, state: bool) -> Result<(), PttError> {
+    let state_file = StateFile { path: path.to_path_buf(), format };
+    state_file.write(state).map_err(|source| PttError::StateFile { path: path.to_path_buf(), source })?;
+    *STATE_FILE.lock().unwrap() = Some(state_file);
+    Ok(())
+}
+
+pub fn capture_state_changed(state: bool) {
+    if let Some(state_file) = STATE_FILE.lock().unwrap().as_ref() {
+        if let Err(e) = state_file.write(state) {
+            warn!("Failed to write state file {}: {}", state_file.path.display(), e);
+        }
+    }
+}
+
+/// Called when exiting, since nobody keeps the state up to date after that
+pub fn remove() {
+    if let Some(state_file) = STATE_FILE.lock().unwrap().take() {
+        let _ = fs::remove_file(&state_file.path);
+    }
+}
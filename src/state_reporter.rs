@@ -0,0 +1,99 @@
+//! Capture state events on stdout for `--output`, e.g. for status bars or a streaming overlay
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+static REPORTER: Mutex<Option<StateReporter>> = Mutex::new(None);
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StateReporter {
+    /// only the log
+    Plain,
+    /// a line of JSON for the current state and every change, like {"ts":1710000000000,"state":"unmuted"}
+    Json,
+    /// the lines of --json-output, like {"event":"unmute","ts":1710000000,"state":true}
+    JsonEvents,
+}
+
+#[derive(Serialize)]
+struct StateEvent {
+    /// Unix time in milliseconds
+    ts: u64,
+    state: &'static str,
+}
+
+/// The mute and unmute events of --json-output, kept as they were for the status bar scripts reading them
+#[derive(Serialize)]
+struct MuteEvent {
+    event: &'static str,
+    /// Unix time in seconds
+    ts: u64,
+    state: bool,
+}
+
+impl StateReporter {
+    /// Whether the events go to stdout, so the log has to go to stderr instead
+    pub fn uses_stdout(self) -> bool {
+        self != StateReporter::Plain
+    }
+
+    fn report(self, state: bool) {
+        let Some(line) = self.line(state, SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()) else {
+            return;
+        };
+        // flushed right away, so a reader at the other end of a pipe sees each change when it happens
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+    }
+
+    /// The line for the state at the Unix time, if the format prints any
+    fn line(self, state: bool, now: Duration) -> Option<String> {
+        let line = match self {
+            StateReporter::Plain => return None,
+            StateReporter::Json => serde_json::to_string(&StateEvent {
+                ts: now.as_millis() as u64,
+                state: if state { "unmuted" } else { "muted" },
+            }),
+            StateReporter::JsonEvents => serde_json::to_string(&MuteEvent {
+                event: if state { "unmute" } else { "mute" },
+                ts: now.as_secs(),
+                state,
+            }),
+        };
+        Some(line.expect("Failed to serialize event"))
+    }
+}
+
+pub fn start(reporter: StateReporter) {
+    *REPORTER.lock().unwrap() = Some(reporter);
+}
+
+/// Reports the state in the format given with `start`
+pub fn capture_state_changed(state: bool) {
+    if let Some(reporter) = *REPORTER.lock().unwrap() {
+        reporter.report(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Duration = Duration::from_millis(1_710_000_005_250);
+
+    #[test]
+    fn json_events_keep_the_json_output_lines() {
+        assert_eq!(StateReporter::JsonEvents.line(true, NOW).as_deref(), Some(r#"{"event":"unmute","ts":1710000005,"state":true}"#));
+        assert_eq!(StateReporter::JsonEvents.line(false, NOW).as_deref(), Some(r#"{"event":"mute","ts":1710000005,"state":false}"#));
+    }
+
+    #[test]
+    fn json_has_milliseconds_and_a_named_state() {
+        assert_eq!(StateReporter::Json.line(true, NOW).as_deref(), Some(r#"{"ts":1710000005250,"state":"unmuted"}"#));
+        assert_eq!(StateReporter::Plain.line(true, NOW), None);
+    }
+}
@@ -0,0 +1,25 @@
+//! Telling systemd about the state of a `Type=notify` service, and pinging its watchdog
+
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tracing::warn;
+
+/// Tells systemd that the setup is done, returning how often to ping its watchdog
+pub fn ready() -> Option<Duration> {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+    // ping twice per watchdog timeout
+    let mut watchdog_usec = 0;
+    sd_notify::watchdog_enabled(false, &mut watchdog_usec).then(|| Duration::from_micros(watchdog_usec / 2))
+}
+
+pub fn watchdog() {
+    let _ = sd_notify::notify(false, &[NotifyState::Watchdog]);
+}
+
+/// Tells systemd that the program is exiting, also after a fatal error, so it does not wait for the watchdog to run out
+pub fn stopping() {
+    let _ = sd_notify::notify(false, &[NotifyState::Stopping]);
+}